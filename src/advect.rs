@@ -0,0 +1,148 @@
+//! Semi-Lagrangian advection and its second order corrections.
+
+use macroquad::prelude::*;
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use crate::field::Field2;
+use crate::sampler::{Boundary, Interpolation, Sampler};
+
+/// Advection scheme for velocity or scalar fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scheme {
+    /// First order backtracing with interpolation of the departure value.
+    #[default]
+    SemiLagrangian,
+    /// A forward and a backward semi-Lagrangian step, corrected by half their round trip error.
+    MacCormack,
+    /// Back and forth error compensation: the round trip error is removed before a final
+    /// semi-Lagrangian step.
+    Bfecc,
+}
+
+/// Integration of the path from a sample back to its departure point.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backtrace {
+    /// A single explicit Euler step with the velocity at the sample.
+    #[default]
+    Euler,
+    /// Midpoint rule, resampling the velocity halfway along the path.
+    Rk2,
+    /// Ralston's third order Runge-Kutta, resampling the velocity twice along the path.
+    Rk3,
+}
+
+/// The velocity field advecting a quantity and how paths through it are integrated.
+pub struct Path<'a> {
+    pub backtrace: Backtrace,
+    pub u: &'a Field2,
+    pub v: &'a Field2,
+    /// Solid mask, 1.0 fluid, 0.0 solid.
+    pub s: &'a Field2,
+    /// Boundary policy of every field sampled along the path.
+    pub boundary: Boundary,
+    pub interpolation: Interpolation,
+}
+
+impl Path<'_> {
+    fn sampler<'a>(&'a self, field: &'a Field2) -> Sampler<'a> {
+        Sampler::new(field, self.s, self.boundary, self.interpolation)
+    }
+
+    /// Velocity at `x` in cell units.
+    fn velocity(&self, x: Vec2) -> Vec2 {
+        vec2(self.sampler(self.u).at(x), self.sampler(self.v).at(x))
+    }
+
+    /// Where the path through `x` with velocity `vel` was `step` cells ago.
+    fn departure(&self, x: Vec2, vel: Vec2, step: f32) -> Vec2 {
+        match self.backtrace {
+            Backtrace::Euler => x - vel * step,
+            Backtrace::Rk2 => {
+                let mid = self.velocity(x - vel * step / 2.0);
+                x - mid * step
+            }
+            Backtrace::Rk3 => {
+                let k2 = self.velocity(x - vel * step / 2.0);
+                let k3 = self.velocity(x - k2 * step * 0.75);
+                x - (vel * 2.0 + k2 * 3.0 + k3 * 4.0) * step / 9.0
+            }
+        }
+    }
+}
+
+/// Advects `field` along `path` by `step = dt / size` cells using `scheme`.
+///
+/// `velocity(i, j)` is the velocity at sample `(i, j)`, or `None` for samples that are kept as
+/// they are, such as solid cells. The corrected schemes are limited to the range of the samples
+/// around the departure point, so they do not create new extrema.
+pub fn advect<V>(field: &mut Field2, scheme: Scheme, path: &Path, velocity: &V, step: f32)
+where
+    V: Fn(usize, usize) -> Option<Vec2> + Sync,
+{
+    let source = field.clone();
+    let offset = field.stagger().offset();
+    let limit = |i: usize, j: usize, vel: Vec2, value: f32| {
+        let x = vec2(i as f32, j as f32) + offset;
+        let (min, max) = path.sampler(&source).bounds(path.departure(x, vel, step));
+        value.clamp(min, max)
+    };
+    match scheme {
+        Scheme::SemiLagrangian => trace(field, &source, path, velocity, step),
+        Scheme::MacCormack => {
+            trace(field, &source, path, velocity, step);
+            let mut back = field.clone();
+            trace(&mut back, field, path, velocity, -step);
+
+            update(field, velocity, |i, j, vel, forward| {
+                let corrected = forward + (source[(i, j)] - back[(i, j)]) / 2.0;
+                limit(i, j, vel, corrected)
+            });
+        }
+        Scheme::Bfecc => {
+            let mut forward = source.clone();
+            trace(&mut forward, &source, path, velocity, step);
+            let mut back = forward.clone();
+            trace(&mut back, &forward, path, velocity, -step);
+
+            let mut corrected = source.clone();
+            update(&mut corrected, velocity, |i, j, _, value| {
+                value + (value - back[(i, j)]) / 2.0
+            });
+            trace(field, &corrected, path, velocity, step);
+
+            update(field, velocity, limit);
+        }
+    }
+}
+
+/// One semi-Lagrangian step: every sample with a velocity takes the value of `source` found
+/// `step` cells upstream along `path`.
+fn trace<V>(target: &mut Field2, source: &Field2, path: &Path, velocity: &V, step: f32)
+where
+    V: Fn(usize, usize) -> Option<Vec2> + Sync,
+{
+    let offset = target.stagger().offset();
+    let source = path.sampler(source);
+    update(target, velocity, |i, j, vel, _| {
+        let x = vec2(i as f32, j as f32) + offset;
+        source.at(path.departure(x, vel, step))
+    });
+}
+
+/// Replaces every sample with a velocity by `f(i, j, velocity, value)`, row by row in parallel.
+fn update<V, F>(target: &mut Field2, velocity: &V, f: F)
+where
+    V: Fn(usize, usize) -> Option<Vec2> + Sync,
+    F: Fn(usize, usize, Vec2, f32) -> f32 + Sync,
+{
+    target.par_rows_mut().for_each(|(j, row)| {
+        for (i, value) in row.iter_mut().enumerate() {
+            if let Some(vel) = velocity(i, j) {
+                *value = f(i, j, vel, *value);
+            }
+        }
+    });
+}
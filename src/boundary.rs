@@ -0,0 +1,290 @@
+//! Conditions on the four sides of the domain: walls, inflows, outflows and open boundaries.
+
+use std::f32::consts::TAU;
+
+use serde::Deserialize;
+
+use crate::config::Side;
+
+/// Shape of an [`Inflow`] across its side.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Profile {
+    /// The same speed along the whole span.
+    #[default]
+    Uniform,
+    /// Full speed in the middle of the span and none at its ends, like a fully developed
+    /// channel flow.
+    Parabolic,
+}
+
+/// Change of the speed of an [`Inflow`] over time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Variation {
+    #[default]
+    Steady,
+    /// Rises linearly from rest to full speed over `duration` seconds, which avoids the pressure
+    /// shock of an impulsive start.
+    Ramp { duration: f32 },
+    /// Oscillates around the speed by `amplitude` times the speed, once every `period` seconds.
+    /// An `amplitude` within `[0, 1]` keeps the flow going into the domain.
+    Pulse { amplitude: f32, period: f32 },
+}
+
+impl Variation {
+    /// Fraction of the full speed at time `t` in seconds.
+    pub(crate) fn factor(self, t: f32) -> f32 {
+        match self {
+            Variation::Steady => 1.0,
+            Variation::Ramp { duration } => (t / duration).min(1.0),
+            Variation::Pulse { amplitude, period } => 1.0 + amplitude * (TAU * t / period).sin(),
+        }
+    }
+}
+
+/// Fluid pushed into the domain through part of a side with a prescribed velocity.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Inflow {
+    /// Peak speed into the domain in m/s, not negative.
+    pub speed: f32,
+    pub profile: Profile,
+    pub variation: Variation,
+    /// Start and end of the inflow as fractions of the side, from the bottom or the left.
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Default for Inflow {
+    fn default() -> Inflow {
+        Inflow::uniform(2.0)
+    }
+}
+
+impl Inflow {
+    /// A steady inflow of `speed` over the whole side.
+    pub fn uniform(speed: f32) -> Inflow {
+        Inflow {
+            speed,
+            profile: Profile::Uniform,
+            variation: Variation::Steady,
+            start: 0.0,
+            end: 1.0,
+        }
+    }
+
+    pub fn with_profile(mut self, profile: Profile) -> Inflow {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_variation(mut self, variation: Variation) -> Inflow {
+        self.variation = variation;
+        self
+    }
+
+    /// Restricts the inflow to the part of the side from `start` to `end`, e.g. a jet.
+    pub fn with_span(mut self, start: f32, end: f32) -> Inflow {
+        self.start = start;
+        self.end = end;
+        self
+    }
+
+    /// Speed into the domain in m/s at time `t` in seconds, at the point `x` along the side as a
+    /// fraction of it.
+    pub fn speed_at(&self, x: f32, t: f32) -> f32 {
+        if !(self.start..=self.end).contains(&x) {
+            return 0.0;
+        }
+        let shape = match self.profile {
+            Profile::Uniform => 1.0,
+            Profile::Parabolic => {
+                let r = (x - self.start) / (self.end - self.start);
+                4.0 * r * (1.0 - r)
+            }
+        };
+        self.speed * shape * self.variation.factor(t)
+    }
+
+    /// Why the inflow cannot be used, if it cannot.
+    pub(crate) fn invalid(&self) -> Option<&'static str> {
+        let finite = self.speed.is_finite() && self.start.is_finite() && self.end.is_finite();
+        if !finite {
+            return Some("inflow speed and span must be finite");
+        }
+        if self.speed < 0.0 {
+            return Some("inflow speed must not be negative, use an outflow to draw fluid out");
+        }
+        if let Variation::Pulse { amplitude, .. } = self.variation {
+            if !(0.0..=1.0).contains(&amplitude) {
+                return Some("pulse amplitude must lie within [0, 1] so the inflow never reverses");
+            }
+        }
+        if !(0.0 <= self.start && self.start < self.end && self.end <= 1.0) {
+            return Some("inflow span must be an increasing range within [0, 1]");
+        }
+        let valid = match self.variation {
+            Variation::Steady => true,
+            Variation::Ramp { duration } => duration > 0.0 && duration.is_finite(),
+            Variation::Pulse { period, .. } => period > 0.0 && period.is_finite(),
+        };
+        (!valid).then_some("ramp duration and pulse period must be positive and finite")
+    }
+}
+
+/// What happens at one side of the domain, see [`SimConfig::boundaries`].
+///
+/// [`SimConfig::boundaries`]: crate::SimConfig::boundaries
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum BoundaryCondition {
+    /// No flow across the side.
+    #[default]
+    Wall,
+    Inflow(Inflow),
+    /// Zero gradient outflow: the velocity across the side is copied from the faces next to it,
+    /// never pointing into the domain, and scaled so that it carries away what the inflows
+    /// bring in.
+    Outflow,
+    /// Convective outflow `∂u/∂t + c ∂u/∂n = 0`, which lets eddies leave the domain with less
+    /// reflection than [`BoundaryCondition::Outflow`] and is scaled the same way. `speed` is `c`
+    /// in m/s, `None` for the mean speed of the outflow.
+    Convective {
+        speed: Option<f32>,
+    },
+    /// Zero pressure just outside the side, so the fluid enters and leaves freely, e.g. the sky
+    /// above a tank.
+    Open,
+}
+
+impl BoundaryCondition {
+    /// Whether fluid can leave through the side.
+    pub fn is_outlet(&self) -> bool {
+        matches!(
+            self,
+            BoundaryCondition::Outflow
+                | BoundaryCondition::Convective { .. }
+                | BoundaryCondition::Open
+        )
+    }
+}
+
+/// Conditions on the four sides of the domain, enforced at the start of every step.
+///
+/// Inflows prescribe the velocity into the domain. Outflow and convective sides take theirs from
+/// the flow next to them and are scaled to carry away what the inflows bring in, unless an open
+/// side lets the pressure balance the two. All pressure solvers support open sides.
+///
+/// In scene files the conditions go in a `[boundaries]` section:
+///
+/// ```toml
+/// [boundaries]
+/// left = { inflow = { speed = 2.0, profile = "parabolic", variation = { ramp = { duration = 1.0 } } } }
+/// right = { convective = {} }
+/// top = "open"
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Boundaries {
+    pub left: BoundaryCondition,
+    pub right: BoundaryCondition,
+    pub bottom: BoundaryCondition,
+    pub top: BoundaryCondition,
+}
+
+impl Default for Boundaries {
+    /// A channel with a uniform inflow on the left and an outflow on the right.
+    fn default() -> Boundaries {
+        Boundaries {
+            left: BoundaryCondition::Inflow(Inflow::default()),
+            right: BoundaryCondition::Outflow,
+            ..Boundaries::walls()
+        }
+    }
+}
+
+impl Boundaries {
+    /// Walls on all sides, a closed tank.
+    pub fn walls() -> Boundaries {
+        Boundaries {
+            left: BoundaryCondition::Wall,
+            right: BoundaryCondition::Wall,
+            bottom: BoundaryCondition::Wall,
+            top: BoundaryCondition::Wall,
+        }
+    }
+
+    pub fn side(&self, side: Side) -> BoundaryCondition {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+            Side::Bottom => self.bottom,
+            Side::Top => self.top,
+        }
+    }
+
+    /// The sides with their conditions.
+    pub fn iter(&self) -> impl Iterator<Item = (Side, BoundaryCondition)> + '_ {
+        [Side::Left, Side::Right, Side::Bottom, Side::Top]
+            .into_iter()
+            .map(|side| (side, self.side(side)))
+    }
+}
+
+/// A face on one side of the domain, see [`side_faces`].
+pub(crate) struct SideFace {
+    /// The cell just inside the side.
+    pub cell: (usize, usize),
+    /// The cell of the border just outside the side.
+    pub outside: (usize, usize),
+    /// The face between the two.
+    pub face: (usize, usize),
+    /// The parallel face on the other side of `cell`.
+    pub inner: (usize, usize),
+    /// Position of the face along the side as a fraction of it, from the bottom or the left.
+    pub x: f32,
+}
+
+/// The faces across `side` of a grid of `width` x `height` cells, corners excluded.
+pub(crate) fn side_faces(side: Side, width: usize, height: usize) -> Vec<SideFace> {
+    let along = |n: usize, count: usize| (n as f32 - 0.5) / count as f32;
+    match side {
+        Side::Left => (1..=height)
+            .map(|j| SideFace {
+                cell: (1, j),
+                outside: (0, j),
+                face: (1, j),
+                inner: (2, j),
+                x: along(j, height),
+            })
+            .collect(),
+        Side::Right => (1..=height)
+            .map(|j| SideFace {
+                cell: (width, j),
+                outside: (width + 1, j),
+                face: (width + 1, j),
+                inner: (width, j),
+                x: along(j, height),
+            })
+            .collect(),
+        Side::Bottom => (1..=width)
+            .map(|i| SideFace {
+                cell: (i, 1),
+                outside: (i, 0),
+                face: (i, 1),
+                inner: (i, 2),
+                x: along(i, width),
+            })
+            .collect(),
+        Side::Top => (1..=width)
+            .map(|i| SideFace {
+                cell: (i, height),
+                outside: (i, height + 1),
+                face: (i, height + 1),
+                inner: (i, height),
+                x: along(i, width),
+            })
+            .collect(),
+    }
+}
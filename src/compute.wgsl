@@ -9,8 +9,44 @@ struct Point {
     nrho: f32,
 }
 
+struct Params {
+    dt: f32,
+    cell_size: f32,
+    gravity: f32,
+    vorticity_confinement: f32,
+    // Cells along x and y, walls included
+    size: vec2<u32>,
+    // Length of `border_faces`
+    border_faces: u32,
+    // Fraction of the full velocity of the border faces of each side, in the order left, right,
+    // bottom, top
+    factors: vec4<f32>,
+}
+
+// A face across a side of the domain, held at a prescribed velocity or copied from the face next
+// to it
+struct BorderFace {
+    index: u32,
+    // The parallel face on the other side of the cell inside
+    inner: u32,
+    side: u32,
+    // 1 for a `u` face, 0 for a `v` face
+    across_u: u32,
+    // 1 to copy the velocity of `inner` as long as it points out of the domain
+    zero_gradient: u32,
+    // Velocity at full speed, or the outward direction for a zero gradient
+    velocity: f32,
+}
+
 @group(0) @binding(0)
 var<storage, read_write> field: array<Point>;
+@group(0) @binding(1)
+var<uniform> params: Params;
+// Bit pattern of the largest face speed, see `measure_speed`
+@group(0) @binding(2)
+var<storage, read_write> max_speed: atomic<u32>;
+@group(0) @binding(3)
+var<storage, read> border_faces: array<BorderFace>;
 @group(1) @binding(0)
 var output: texture_storage_2d<rgba8unorm, write>;
 
@@ -21,34 +57,137 @@ struct Ids {
     @builtin(num_workgroups) num_groups: vec3<u32>,
 }
 
-fn get_id(global_id: vec3<u32>, num_groups: vec3<u32>, group_size: u32) -> u32 {
-    let row = num_groups.x * group_size;
-    return global_id.y * row + global_id.x;
+// Whether invocation `id` lies outside the grid, which the workgroups overhang unless its size is
+// a multiple of 16
+fn outside(id: vec3<u32>) -> bool {
+    return any(id.xy >= params.size);
+}
+
+// Whether cell `id` is at least `margin` cells away from the border of the grid
+fn inside(id: vec3<u32>, margin: u32) -> bool {
+    return all(id.xy >= vec2(margin)) && all(id.xy + margin < params.size);
+}
+
+// Sets the faces across the sides of the domain at the start of each step, like
+// `Grid::enforce_boundaries`
+@compute @workgroup_size(64)
+fn enforce_boundaries(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.border_faces {
+        return;
+    }
+    let face = border_faces[id.x];
+    var velocity = face.velocity * params.factors[face.side];
+    if face.zero_gradient == 1u {
+        var inner = field[face.inner].v;
+        if face.across_u == 1u {
+            inner = field[face.inner].u;
+        }
+        velocity = face.velocity * max(face.velocity * inner, 0.0);
+    }
+    if face.across_u == 1u {
+        field[face.index].u = velocity;
+    } else {
+        field[face.index].v = velocity;
+    }
 }
 
 @compute @workgroup_size(16, 16)
 fn integrate(ids: Ids) {
-    // TODO pass dt & g as constants
-    let dt = 0.01;
-    let g = -9.81;
-    // TODO pass size as a constant
-    let row_size = 256u;
+    let dt = params.dt;
+    let g = params.gravity;
+    let row_size = params.size.x;
     let id = ids.global_id;
+    if outside(id) {
+        return;
+    }
     let index = id.y * row_size + id.x;
-    if field[index].s == 1.0 && field[index - row_size].s == 1.0 {
+    // Cells beyond open sides are fluid, but their faces are left to the boundaries
+    if field[index].s == 1.0 && inside(id, 1u) && field[index - row_size].s == 1.0 {
         field[index].v += g * dt;
     }
 }
 
+fn cell_velocity(index: u32, row_size: u32) -> vec2<f32> {
+    let u = (field[index].u + field[index + 1u].u) / 2.0;
+    let v = (field[index].v + field[index + row_size].v) / 2.0;
+    return vec2(u, v);
+}
+
+// Vorticity confinement, in three passes: the curl is stored in `avg_div` and the force in `nu`
+// and `nv`, which are free until the projection and advection overwrite them. Cells next to the
+// walls are left alone.
+@compute @workgroup_size(16, 16)
+fn curl(ids: Ids) {
+    let row_size = params.size.x;
+    let id = ids.global_id;
+    if outside(id) {
+        return;
+    }
+    let index = id.y * row_size + id.x;
+    field[index].avg_div = 0.0;
+    if field[index].s == 1.0 && inside(id, 2u) {
+        let dv_dx = cell_velocity(index + 1u, row_size).y - cell_velocity(index - 1u, row_size).y;
+        let du_dy = cell_velocity(index + row_size, row_size).x - cell_velocity(index - row_size, row_size).x;
+        field[index].avg_div = (dv_dx - du_dy) / (2.0 * params.cell_size);
+    }
+}
+
+@compute @workgroup_size(16, 16)
+fn confinement_force(ids: Ids) {
+    let row_size = params.size.x;
+    let id = ids.global_id;
+    if outside(id) {
+        return;
+    }
+    let index = id.y * row_size + id.x;
+    field[index].nu = 0.0;
+    field[index].nv = 0.0;
+    if field[index].s == 1.0 && inside(id, 3u) {
+        let gradient = vec2(
+            abs(field[index + 1u].avg_div) - abs(field[index - 1u].avg_div),
+            abs(field[index + row_size].avg_div) - abs(field[index - row_size].avg_div),
+        );
+        let length = length(gradient);
+        if length > 0.0 {
+            let n = gradient / length;
+            let w = field[index].avg_div;
+            let force = params.vorticity_confinement * params.cell_size * vec2(n.y * w, -n.x * w);
+            field[index].nu = force.x;
+            field[index].nv = force.y;
+        }
+    }
+}
+
+@compute @workgroup_size(16, 16)
+fn apply_confinement(ids: Ids) {
+    let row_size = params.size.x;
+    let id = ids.global_id;
+    if outside(id) {
+        return;
+    }
+    let index = id.y * row_size + id.x;
+    let dt = params.dt;
+    if field[index].s == 1.0 && inside(id, 1u) {
+        if field[index - 1u].s == 1.0 {
+            field[index].u += dt * (field[index - 1u].nu + field[index].nu) / 2.0;
+        }
+        if field[index - row_size].s == 1.0 {
+            field[index].v += dt * (field[index - row_size].nv + field[index].nv) / 2.0;
+        }
+    }
+}
+
 @compute @workgroup_size(16, 16)
 fn gather(ids: Ids) {
-    // TODO pass size as a constant
-    let row_size = 256u;
+    let row_size = params.size.x;
     let id = ids.global_id;
+    if outside(id) {
+        return;
+    }
     let index = id.y * row_size + id.x;
-    //let index = get_id(ids.global_id, ids.num_groups, 1u);
     let here = &field[index];
-    if (*here).s == 1.0 {
+    // Cells beyond open sides are fluid, but their pressure stays at zero
+    if (*here).s == 1.0 && inside(id, 1u) {
         let left = field[index - 1u];
         let right = field[index + 1u];
         let bottom = field[index - row_size];
@@ -56,15 +195,20 @@ fn gather(ids: Ids) {
         let s = left.s + right.s + bottom.s + top.s;
         let avg_div = (right.u - (*here).u + top.v - (*here).v) / s;
         (*here).avg_div = avg_div;
+    } else {
+        (*here).avg_div = 0.0;
     }
 }
 
 @compute @workgroup_size(16, 16)
 fn scatter_bottom_left(ids: Ids) {
-    let row_size = 256u;
+    let row_size = params.size.x;
     let id = ids.global_id;
+    if outside(id) {
+        return;
+    }
     let index = id.y * row_size + id.x;
-    if field[index].s == 1.0 {
+    if field[index].s == 1.0 && inside(id, 1u) {
         let left = field[index - 1u];
         let bottom = field[index - row_size];
         let avg_div = field[index].avg_div;
@@ -75,10 +219,13 @@ fn scatter_bottom_left(ids: Ids) {
 
 @compute @workgroup_size(16, 16)
 fn scatter_top_right(ids: Ids) {
-    let row_size = 256u;
+    let row_size = params.size.x;
     let id = ids.global_id;
+    if outside(id) {
+        return;
+    }
     let index = id.y * row_size + id.x;
-    if field[index].s == 1.0 {
+    if field[index].s == 1.0 && inside(id, 1u) {
         let right = &field[index + 1u];
         let top = &field[index + row_size];
         let avg_div = field[index].avg_div;
@@ -89,24 +236,40 @@ fn scatter_top_right(ids: Ids) {
 
 var<workgroup> foo: atomic<u32>;
 
+@compute @workgroup_size(16, 16)
+fn measure_speed(ids: Ids) {
+    let row_size = params.size.x;
+    let id = ids.global_id;
+    if outside(id) {
+        return;
+    }
+    let index = id.y * row_size + id.x;
+    let speed = max(abs(field[index].u), abs(field[index].v));
+    // Non-negative floats are ordered like their bit patterns
+    atomicMax(&max_speed, bitcast<u32>(speed));
+}
+
 @compute @workgroup_size(16, 16)
 fn advect_u(ids: Ids) {
-    let dt = 0.01;
-    let cell_size = 0.1;
-    let row_size = 256u;
+    let dt = params.dt;
+    let cell_size = params.cell_size;
+    let row_size = params.size.x;
     let id = ids.global_id;
+    if outside(id) {
+        return;
+    }
     let index = id.y * row_size + id.x;
     let here = field[index];
     let p = &foo;
     let val = atomicLoad(p);
     field[index].nu = here.u;
-    if here.s == 1.0 && field[index - 1u].s == 1.0 {
+    if here.s == 1.0 && inside(id, 1u) && field[index - 1u].s == 1.0 {
         let u = here.u;
         let v = (here.v + field[index - 1u].v + field[index + row_size].v + field[index + row_size - 1u].v) / 4.0;
         // TODO try abstracting field sampling
         let p = vec2<f32>(ids.global_id.xy) - vec2(u, v) * dt / cell_size;
         let pf = vec2<u32>(floor(p));
-        let p_id = clamp(vec2<u32>(floor(p)), vec2(1u, 1u), vec2(row_size - 1u, row_size - 1u));
+        let p_id = clamp(vec2<u32>(floor(p)), vec2(1u, 1u), params.size - 2u);
         let b = p - floor(p);
 //        let a = 1.0 - b;
         let a = vec2(1.0, 1.0) - b;
@@ -122,18 +285,21 @@ fn advect_u(ids: Ids) {
 
 @compute @workgroup_size(16, 16)
 fn advect_v(ids: Ids) {
-    let dt = 0.01;
-    let cell_size = 0.1;
-    let row_size = 256u;
+    let dt = params.dt;
+    let cell_size = params.cell_size;
+    let row_size = params.size.x;
     let id = ids.global_id;
+    if outside(id) {
+        return;
+    }
     let index = id.y * row_size + id.x;
     let here = field[index];
     field[index].nv = here.v;
-    if here.s == 1.0 && field[index - row_size].s == 1.0 {
+    if here.s == 1.0 && inside(id, 1u) && field[index - row_size].s == 1.0 {
         let u = (here.u + field[index - row_size].u + field[index + 1u].u + field[index - row_size + 1u].u) / 4.0;
         let v = here.v;
         let p = vec2<f32>(ids.global_id.xy) - vec2(u, v) * dt / cell_size;
-        let p_id = clamp(vec2<u32>(floor(p)), vec2(1u, 1u), vec2(row_size - 1u, row_size - 1u));
+        let p_id = clamp(vec2<u32>(floor(p)), vec2(1u, 1u), params.size - 2u);
         let b = p - floor(p);
         let a = 1.0 - b;
         let p_index = p_id.y * row_size + p_id.x;
@@ -148,18 +314,21 @@ fn advect_v(ids: Ids) {
 
 @compute @workgroup_size(16, 16)
 fn advect_density(ids: Ids) {
-    let dt = 0.01;
-    let cell_size = 0.1;
-    let row_size = 256u;
+    let dt = params.dt;
+    let cell_size = params.cell_size;
+    let row_size = params.size.x;
     let id = ids.global_id;
+    if outside(id) {
+        return;
+    }
     let index = id.y * row_size + id.x;
     let here = field[index];
     field[index].nrho = here.rho;
-    if here.s == 1.0 {
+    if here.s == 1.0 && inside(id, 1u) {
         let u = (here.u + field[index + 1u].u) / 2.0;
         let v = (here.v + field[index + row_size].v) / 2.0;
         let p = vec2<f32>(ids.global_id.xy) - vec2(u, v) * dt / cell_size;
-        let p_id = clamp(vec2<u32>(floor(p)), vec2(1u, 1u), vec2(row_size - 1u, row_size - 1u));
+        let p_id = clamp(vec2<u32>(floor(p)), vec2(1u, 1u), params.size - 2u);
         let b = p - floor(p);
         let a = 1.0 - b;
         let p_index = p_id.y * row_size + p_id.x;
@@ -174,7 +343,10 @@ fn advect_density(ids: Ids) {
 
 @compute @workgroup_size(16, 16)
 fn copy(ids: Ids) {
-    let id = get_id(ids.global_id, ids.num_groups, 16u);
+    if outside(ids.global_id) {
+        return;
+    }
+    let id = ids.global_id.y * params.size.x + ids.global_id.x;
     let f = field[id];
     field[id].u = f.nu;
     field[id].v = f.nv;
@@ -0,0 +1,517 @@
+use std::fmt;
+
+use macroquad::prelude::*;
+use serde::Deserialize;
+
+use crate::advect::{Backtrace, Scheme};
+use crate::boundary::{Boundaries, BoundaryCondition};
+use crate::flip::Flip;
+use crate::level_set::Liquid;
+use crate::mask::{Marking, Mask};
+use crate::obstacle::Obstacle;
+use crate::pressure::{ResidualNorm, Solver};
+use crate::sampler::{Boundary, Interpolation};
+use crate::scalar::{Emitter, Region, Scalar};
+use crate::tracer::{Seed, Tracers};
+
+/// Physical and numerical parameters of a simulation, passed to [`Grid::new`] or read from a
+/// scene file with [`Scene::load`].
+///
+/// Each step of the CPU grid enforces the [`boundaries`](SimConfig::boundaries), applies gravity,
+/// buoyancy, vorticity confinement and viscosity, projects the velocity with the pressure
+/// `solver`, and then advects the velocity, the scalars and the tracers through it.
+///
+/// [`Grid::new`]: crate::Grid::new
+/// [`Scene::load`]: crate::Scene::load
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimConfig {
+    /// Vertical acceleration in m/s², negative points down.
+    pub gravity: f32,
+    /// Fluid density in kg/m³, only scales the reported pressure.
+    pub density: f32,
+    /// Kinematic viscosity in m²/s, 0 for inviscid flow. The Reynolds number of the wake is
+    /// `speed * 2 * radius * size / viscosity` for an inflow of `speed`. The velocity is
+    /// diffused implicitly with no-slip walls, solved with the preconditioned conjugate gradient
+    /// of [`Solver::Pcg`].
+    pub viscosity: f32,
+    /// Strength of the vorticity confinement force, 0 to disable it. Values around 0.1 to 1 keep
+    /// the wake behind the obstacle lively at coarse resolutions.
+    pub vorticity_confinement: f32,
+    /// Linear solver of the pressure projection.
+    pub solver: Solver,
+    /// Over-relaxation factor of the SOR pressure solver, in `(0, 2)`.
+    pub over_relaxation: f32,
+    /// Pressure solver iterations per step, or the cap on iterations when `tolerance` is set.
+    pub iterations: usize,
+    /// Stop the pressure solver once the divergence residual drops below this, in 1/s.
+    pub tolerance: Option<f32>,
+    /// Norm of the divergence residual compared against `tolerance`.
+    pub residual_norm: ResidualNorm,
+    /// Advection scheme of the velocity.
+    pub velocity_advection: Scheme,
+    /// Particles carrying the velocity instead of `velocity_advection`, `None` to advect the
+    /// velocity on the grid. They keep eddies alive much longer, at the cost of some noise.
+    pub flip: Option<Flip>,
+    /// Advection scheme of the density and other transported scalars.
+    pub scalar_advection: Scheme,
+    /// Integration of the departure points of the velocity.
+    pub velocity_backtrace: Backtrace,
+    /// Integration of the departure points of the density and other transported scalars.
+    pub scalar_backtrace: Backtrace,
+    /// How fields are sampled near walls and obstacles during advection.
+    pub boundary: Boundary,
+    /// Interpolation kernel of the advection of velocity and scalars.
+    pub interpolation: Interpolation,
+    /// Largest distance in cells the fluid may move per substep of [`Grid::advance`].
+    ///
+    /// [`Grid::advance`]: crate::Grid::advance
+    pub cfl: f32,
+    /// Worker threads for advection and the `RedBlack` solver, 0 for one per core.
+    /// Results do not depend on the number of threads.
+    pub threads: usize,
+    /// Conditions on the four sides of the domain, by default a channel from left to right.
+    pub boundaries: Boundaries,
+    /// Scene geometry loaded from an image, painted over the walls, with the colors of
+    /// [`Marking`](crate::Marking). Its inflow cells blow into their fluid neighbours and its
+    /// outflow cells draw out of them at `mask_speed`. Outflow and convective sides carry away
+    /// what they bring in net, and inflow cells need those or outflow cells to leave through.
+    pub mask: Option<Mask>,
+    /// Circular obstacle, `None` for an unobstructed channel.
+    pub obstacle: Option<Disk>,
+    /// Obstacles moving with a prescribed velocity, e.g. a towed cylinder or a paddle.
+    pub obstacles: Vec<Obstacle>,
+    /// Velocity of the fluid at the start inside regions, at rest elsewhere.
+    pub initial_velocity: Vec<InitialVelocity>,
+    /// Initial liquid of a free surface simulation, where the pressure is only solved in the
+    /// liquid. Empty to fill the whole domain with fluid. [`Solver::Pcg`] and
+    /// [`Solver::Multigrid`] place the zero pressure exactly at the surface with the ghost fluid
+    /// method, the other solvers at the centers of the air cells.
+    pub liquid: Vec<Liquid>,
+    /// Buoyancy from temperature and smoke scalars, `None` to keep all scalars passive.
+    pub buoyancy: Option<Buoyancy>,
+    /// Passive scalars carried by the flow in addition to the density.
+    pub scalars: Vec<Scalar>,
+    /// Marker particles carried by the flow.
+    pub tracers: Tracers,
+    /// Speed of the flow out of the inflow cells and into the outflow cells of `mask` in m/s.
+    pub mask_speed: f32,
+}
+
+impl Default for SimConfig {
+    fn default() -> SimConfig {
+        SimConfig {
+            gravity: -9.81,
+            density: 1000.0,
+            viscosity: 0.0,
+            vorticity_confinement: 0.0,
+            solver: Solver::Sor,
+            over_relaxation: 1.9,
+            iterations: 100,
+            tolerance: None,
+            residual_norm: ResidualNorm::Max,
+            velocity_advection: Scheme::SemiLagrangian,
+            flip: None,
+            scalar_advection: Scheme::SemiLagrangian,
+            velocity_backtrace: Backtrace::Euler,
+            scalar_backtrace: Backtrace::Euler,
+            boundary: Boundary::Clamp,
+            interpolation: Interpolation::Linear,
+            cfl: 5.0,
+            threads: 0,
+            boundaries: Boundaries::default(),
+            mask: None,
+            obstacle: Some(Disk {
+                center: vec2(0.2, 0.5),
+                radius: 15.0,
+            }),
+            obstacles: Vec::new(),
+            initial_velocity: Vec::new(),
+            liquid: Vec::new(),
+            buoyancy: None,
+            scalars: Vec::new(),
+            tracers: Tracers::default(),
+            mask_speed: 2.0,
+        }
+    }
+}
+
+impl SimConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let finite = [
+            ("gravity", self.gravity),
+            ("density", self.density),
+            ("over_relaxation", self.over_relaxation),
+            ("mask_speed", self.mask_speed),
+            ("viscosity", self.viscosity),
+            ("vorticity_confinement", self.vorticity_confinement),
+        ];
+        if let Some(&(name, _)) = finite.iter().find(|(_, value)| !value.is_finite()) {
+            return Err(ConfigError::NotFinite(name));
+        }
+        if self.density <= 0.0 {
+            return Err(ConfigError::NonPositiveDensity(self.density));
+        }
+        if self.viscosity < 0.0 {
+            return Err(ConfigError::NegativeViscosity(self.viscosity));
+        }
+        if self.vorticity_confinement < 0.0 {
+            return Err(ConfigError::NegativeVorticityConfinement(
+                self.vorticity_confinement,
+            ));
+        }
+        if self.over_relaxation <= 0.0 || self.over_relaxation >= 2.0 {
+            return Err(ConfigError::OverRelaxation(self.over_relaxation));
+        }
+        if self.iterations == 0 {
+            return Err(ConfigError::NoIterations);
+        }
+        if !(self.cfl > 0.0 && self.cfl.is_finite()) {
+            return Err(ConfigError::Cfl(self.cfl));
+        }
+        if let Some(tolerance) = self.tolerance {
+            if !(tolerance > 0.0 && tolerance.is_finite()) {
+                return Err(ConfigError::Tolerance(tolerance));
+            }
+        }
+        if let Some(flip) = self.flip {
+            if !(0.0..=1.0).contains(&flip.ratio) || flip.per_axis == 0 {
+                return Err(ConfigError::Flip(flip));
+            }
+        }
+        self.validate_boundaries()?;
+        if let Some(mask) = &self.mask {
+            let (width, height) = mask.size();
+            if width == 0 || height == 0 {
+                return Err(ConfigError::EmptyMask);
+            }
+        }
+        if let Some(disk) = &self.obstacle {
+            if !disk.is_valid() {
+                return Err(ConfigError::Obstacle(*disk));
+            }
+        }
+        if let Some(obstacle) = self.obstacles.iter().find(|o| !o.is_valid()) {
+            return Err(ConfigError::MovingObstacle(*obstacle));
+        }
+        for liquid in &self.liquid {
+            let valid = match liquid {
+                Liquid::Rect { min, max } => {
+                    let unit = 0.0..=1.0;
+                    let corners = [min.x, min.y, max.x, max.y];
+                    corners.iter().all(|c| unit.contains(c)) && min.x < max.x && min.y < max.y
+                }
+                Liquid::Disk(disk) => disk.is_valid(),
+            };
+            if !valid {
+                return Err(ConfigError::Liquid(*liquid));
+            }
+        }
+        for (index, scalar) in self.scalars.iter().enumerate() {
+            let error = |reason| ConfigError::Scalar {
+                name: scalar.name.clone(),
+                reason,
+            };
+            if self.scalars[..index].iter().any(|s| s.name == scalar.name) {
+                return Err(error("name is used by another scalar"));
+            }
+            if !(scalar.diffusion >= 0.0 && scalar.diffusion.is_finite()) {
+                return Err(error("diffusion must be non-negative and finite"));
+            }
+            let valid = |emitter: &Emitter| emitter.region.is_valid() && emitter.value.is_finite();
+            if !scalar.emitters.iter().all(valid) {
+                return Err(error(
+                    "emitters must be finite and lie within the unit square",
+                ));
+            }
+            if !scalar.initial.iter().all(valid) {
+                return Err(error(
+                    "initial values must be finite and lie within the unit square",
+                ));
+            }
+            let regions = scalar.emitters.iter().chain(&scalar.initial);
+            if regions.map(|e| e.region).any(|r| r == Region::Mask) && self.mask.is_none() {
+                return Err(error(
+                    "refers to the dye cells of the mask, but there is none",
+                ));
+            }
+        }
+        for initial in &self.initial_velocity {
+            let valid = initial.region.is_valid() && initial.velocity.is_finite();
+            if !valid || (initial.region == Region::Mask && self.mask.is_none()) {
+                return Err(ConfigError::InitialVelocity(*initial));
+            }
+        }
+        for seed in &self.tracers.seeds {
+            let points = match seed {
+                Seed::Line { start, end, .. } => vec![*start, *end],
+                Seed::Emitter { position } => vec![*position],
+            };
+            let unit = 0.0..=1.0;
+            if !points
+                .iter()
+                .all(|p| unit.contains(&p.x) && unit.contains(&p.y))
+            {
+                return Err(ConfigError::Seed(*seed));
+            }
+        }
+        if let Some(buoyancy) = &self.buoyancy {
+            buoyancy.validate(&self.scalars)?;
+        }
+        Ok(())
+    }
+
+    fn validate_boundaries(&self) -> Result<(), ConfigError> {
+        let boundaries = &self.boundaries;
+        for (side, condition) in boundaries.iter() {
+            let reason = match condition {
+                BoundaryCondition::Inflow(inflow) => inflow.invalid(),
+                BoundaryCondition::Convective { speed: Some(c) }
+                    if !(c >= 0.0 && c.is_finite()) =>
+                {
+                    Some("convective speed must be non-negative and finite")
+                }
+                _ => None,
+            };
+            if let Some(reason) = reason {
+                return Err(ConfigError::Boundary { side, reason });
+            }
+        }
+        let inflow = boundaries
+            .iter()
+            .find(|(_, condition)| matches!(condition, BoundaryCondition::Inflow(_)));
+        if let Some((side, _)) = inflow {
+            if !boundaries
+                .iter()
+                .any(|(_, condition)| condition.is_outlet())
+            {
+                let reason = "an inflow needs an outflow or open side to leave through";
+                return Err(ConfigError::Boundary { side, reason });
+            }
+        }
+        let mask_inflow = self
+            .mask
+            .as_ref()
+            .is_some_and(|mask| mask.contains(Marking::Inflow) && !mask.contains(Marking::Outflow));
+        if mask_inflow
+            && !boundaries
+                .iter()
+                .any(|(_, condition)| condition.is_outlet())
+        {
+            return Err(ConfigError::MaskInflow);
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a grid of `width` x `height` cells of side `size` has fluid cells of a usable
+/// size.
+pub(crate) fn validate_grid(width: usize, height: usize, size: f32) -> Result<(), ConfigError> {
+    if width == 0 || height == 0 {
+        return Err(ConfigError::EmptyGrid { width, height });
+    }
+    if !(size > 0.0 && size.is_finite()) {
+        return Err(ConfigError::NonPositiveCellSize(size));
+    }
+    Ok(())
+}
+
+/// Boussinesq buoyancy: hot fluid rises and smoke sinks with a force proportional to the
+/// temperature above ambient and to the smoke density, while the density of the fluid is
+/// otherwise constant. Both are read from scalars of [`SimConfig::scalars`], so heat sources are
+/// emitters and heat conduction is their diffusion.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Buoyancy {
+    /// Name of the scalar holding the temperature.
+    pub temperature: String,
+    /// Name of the scalar holding the smoke density, `None` without smoke.
+    pub smoke: Option<String>,
+    /// Temperature at which the fluid neither rises nor sinks.
+    pub ambient: f32,
+    /// Downward acceleration per unit of smoke density in m/s².
+    pub alpha: f32,
+    /// Upward acceleration per degree above `ambient` in m/s²/K.
+    pub beta: f32,
+    /// Amplitude in K of a fixed random pattern added to what the temperature emitters hold.
+    /// Uniformly heated walls give a horizontally uniform force that the projection removes, so
+    /// convection cells only form once something breaks the symmetry.
+    pub perturbation: f32,
+}
+
+impl Default for Buoyancy {
+    fn default() -> Buoyancy {
+        Buoyancy {
+            temperature: "temperature".to_string(),
+            smoke: None,
+            ambient: 0.0,
+            alpha: 0.1,
+            beta: 1.0,
+            perturbation: 0.01,
+        }
+    }
+}
+
+impl Buoyancy {
+    fn validate(&self, scalars: &[Scalar]) -> Result<(), ConfigError> {
+        let finite = [
+            ("buoyancy.ambient", self.ambient),
+            ("buoyancy.alpha", self.alpha),
+            ("buoyancy.beta", self.beta),
+            ("buoyancy.perturbation", self.perturbation),
+        ];
+        if let Some(&(name, _)) = finite.iter().find(|(_, value)| !value.is_finite()) {
+            return Err(ConfigError::NotFinite(name));
+        }
+        for name in std::iter::once(&self.temperature).chain(&self.smoke) {
+            if !scalars.iter().any(|scalar| &scalar.name == name) {
+                return Err(ConfigError::UnknownScalar(name.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A velocity the fluid starts with inside a region, e.g. a jet already under way.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InitialVelocity {
+    pub region: Region,
+    /// Velocity in m/s.
+    pub velocity: Vec2,
+}
+
+/// A disk, e.g. an obstacle. The center is given as a fraction of the domain size, the radius in
+/// cells.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Disk {
+    pub center: Vec2,
+    pub radius: f32,
+}
+
+/// One of the four sides of the domain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Side {
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+impl Side {
+    /// Sign of the velocity across the side that points out of the domain.
+    pub(crate) fn outward(self) -> f32 {
+        match self {
+            Side::Left | Side::Bottom => -1.0,
+            Side::Right | Side::Top => 1.0,
+        }
+    }
+}
+
+impl Disk {
+    /// Whether the center lies within the domain and the radius is non-negative.
+    pub(crate) fn is_valid(&self) -> bool {
+        let unit = 0.0..=1.0;
+        let inside = unit.contains(&self.center.x) && unit.contains(&self.center.y);
+        inside && self.radius >= 0.0 && self.radius.is_finite()
+    }
+
+    /// Center and radius in cell units on a grid of `width` x `height` cells.
+    pub fn in_cells(&self, width: usize, height: usize) -> (Vec2, f32) {
+        (self.center * vec2(width as f32, height as f32), self.radius)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    EmptyGrid { width: usize, height: usize },
+    NonPositiveCellSize(f32),
+    NotFinite(&'static str),
+    NonPositiveDensity(f32),
+    NegativeViscosity(f32),
+    NegativeVorticityConfinement(f32),
+    OverRelaxation(f32),
+    NoIterations,
+    Tolerance(f32),
+    Cfl(f32),
+    Flip(Flip),
+    Boundary { side: Side, reason: &'static str },
+    EmptyMask,
+    MaskInflow,
+    Obstacle(Disk),
+    MovingObstacle(Obstacle),
+    InitialVelocity(InitialVelocity),
+    Liquid(Liquid),
+    Seed(Seed),
+    Scalar { name: String, reason: &'static str },
+    UnknownScalar(String),
+    ThreadPool(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::EmptyGrid { width, height } => {
+                write!(f, "grid of {width}x{height} cells has no fluid cells")
+            }
+            ConfigError::NonPositiveCellSize(size) => {
+                write!(f, "cell size must be positive, got {size}")
+            }
+            ConfigError::NotFinite(name) => write!(f, "{name} must be finite"),
+            ConfigError::NonPositiveDensity(density) => {
+                write!(f, "density must be positive, got {density}")
+            }
+            ConfigError::NegativeViscosity(viscosity) => {
+                write!(f, "viscosity must not be negative, got {viscosity}")
+            }
+            ConfigError::NegativeVorticityConfinement(epsilon) => {
+                write!(f, "vorticity confinement must not be negative, got {epsilon}")
+            }
+            ConfigError::OverRelaxation(omega) => {
+                write!(f, "over-relaxation must be in (0, 2), got {omega}")
+            }
+            ConfigError::NoIterations => write!(f, "pressure solver needs at least one iteration"),
+            ConfigError::Tolerance(tolerance) => {
+                write!(f, "tolerance must be positive and finite, got {tolerance}")
+            }
+            ConfigError::Cfl(cfl) => {
+                write!(f, "CFL number must be positive and finite, got {cfl}")
+            }
+            ConfigError::Seed(seed) => {
+                write!(f, "tracer seed {seed:?} must lie within the unit square")
+            }
+            ConfigError::Flip(flip) => write!(
+                f,
+                "FLIP ratio {} must be in [0, 1] and there must be at least one particle per axis",
+                flip.ratio
+            ),
+            ConfigError::Boundary { side, reason } => write!(f, "{side:?} boundary: {reason}"),
+            ConfigError::EmptyMask => write!(f, "mask image must not be empty"),
+            ConfigError::MaskInflow => write!(
+                f,
+                "mask inflow cells need outflow cells or an outflow or open side to leave through"
+            ),
+            ConfigError::Obstacle(disk) => write!(
+                f,
+                "obstacle center {} must lie within the unit square and radius {} must be non-negative",
+                disk.center, disk.radius
+            ),
+            ConfigError::MovingObstacle(obstacle) => write!(
+                f,
+                "moving obstacle {obstacle:?} must start within the unit square with a finite motion and non-negative size"
+            ),
+            ConfigError::InitialVelocity(initial) => write!(
+                f,
+                "initial velocity {initial:?} must be finite and lie within the unit square or the mask"
+            ),
+            ConfigError::Liquid(liquid) => write!(
+                f,
+                "liquid {liquid:?} must lie within the unit square and must not be empty"
+            ),
+            ConfigError::Scalar { name, reason } => write!(f, "scalar {name:?}: {reason}"),
+            ConfigError::UnknownScalar(name) => write!(f, "no scalar is named {name:?}"),
+            ConfigError::ThreadPool(err) => write!(f, "failed to start worker threads: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
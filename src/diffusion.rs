@@ -0,0 +1,57 @@
+//! Implicit diffusion of velocities and scalars, solved with the PCG solver of the pressure
+//! projection.
+
+use crate::field::Field2;
+use crate::pressure::pcg::{self, Matrix5};
+use crate::pressure::{self, ResidualNorm, RELATIVE_TOLERANCE};
+
+/// What happens at the samples next to the ones being diffused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    /// They keep their values and diffuse into the field, e.g. no-slip walls and the inflow.
+    Dirichlet,
+    /// Nothing diffuses across them, e.g. a dye at an insulating wall.
+    Neumann,
+}
+
+/// One backward Euler step of `∂f/∂t = k ∇²f` on the samples of `field` for which `unknown(i, j)`
+/// holds, where `a = k dt / h²`. Returns the PCG iterations used.
+pub fn diffuse(
+    field: &mut Field2,
+    unknown: impl Fn(usize, usize) -> bool,
+    a: f32,
+    condition: Condition,
+    max_iterations: usize,
+) -> usize {
+    let row = field.nx();
+    let unknown: Vec<bool> = (0..field.data().len())
+        .map(|k| unknown(k % row, k / row))
+        .collect();
+    let a = a as f64;
+    let matrix = Matrix5::diffusion(&unknown, row, a, condition);
+
+    let values = field.data();
+    let mut b = vec![0.0; values.len()];
+    for k in (0..b.len()).filter(|&k| unknown[k]) {
+        b[k] = values[k] as f64;
+        if condition == Condition::Dirichlet {
+            for n in [k - 1, k + 1, k - row, k + row] {
+                if !unknown[n] {
+                    b[k] += a * values[n] as f64;
+                }
+            }
+        }
+    }
+
+    let norm = ResidualNorm::Max;
+    let tolerance = RELATIVE_TOLERANCE * pressure::norm(&b, |k| unknown[k], norm);
+    let mut x = vec![0.0; b.len()];
+    let (iterations, _) = pcg::solve(&matrix, &b, &mut x, tolerance, norm, max_iterations);
+
+    for (k, value) in field.data_mut().iter_mut().enumerate() {
+        if unknown[k] {
+            *value = x[k] as f32;
+        }
+    }
+    iterations
+}
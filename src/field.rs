@@ -0,0 +1,134 @@
+use std::ops::{Index, IndexMut};
+
+use macroquad::prelude::*;
+use rayon::prelude::*;
+
+/// Where the samples of a field live within their cell on the MAC grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stagger {
+    /// Cell centers, e.g. pressure, density and the solid mask.
+    Center,
+    /// Vertical faces, `(i, j)` is the left face of cell `(i, j)`, e.g. `u`.
+    XFace,
+    /// Horizontal faces, `(i, j)` is the bottom face of cell `(i, j)`, e.g. `v`.
+    YFace,
+}
+
+impl Stagger {
+    /// Position of sample `(0, 0)` relative to the center of cell `(0, 0)`, in cell units.
+    pub fn offset(self) -> Vec2 {
+        match self {
+            Stagger::Center => vec2(0.0, 0.0),
+            Stagger::XFace => vec2(-0.5, 0.0),
+            Stagger::YFace => vec2(0.0, -0.5),
+        }
+    }
+}
+
+/// A contiguous, row-major 2D field of `nx` x `ny` samples.
+///
+/// Sample `(i, j)` is stored at `j * nx + i`, the same layout as the GPU `Point` buffer.
+#[derive(Clone, Debug)]
+pub struct Field2 {
+    nx: usize,
+    ny: usize,
+    stagger: Stagger,
+    data: Vec<f32>,
+}
+
+impl Field2 {
+    pub fn new(nx: usize, ny: usize, stagger: Stagger, value: f32) -> Field2 {
+        Field2 {
+            nx,
+            ny,
+            stagger,
+            data: vec![value; nx * ny],
+        }
+    }
+
+    pub fn nx(&self) -> usize {
+        self.nx
+    }
+
+    pub fn ny(&self) -> usize {
+        self.ny
+    }
+
+    pub fn stagger(&self) -> Stagger {
+        self.stagger
+    }
+
+    /// Flat index of sample `(i, j)`.
+    #[inline]
+    pub fn index(&self, i: usize, j: usize) -> usize {
+        debug_assert!(i < self.nx, "i = {i} out of bounds 0..{}", self.nx);
+        debug_assert!(j < self.ny, "j = {j} out of bounds 0..{}", self.ny);
+        j * self.nx + i
+    }
+
+    /// Whether flat index `k` lies on the outermost ring of samples.
+    pub fn is_border(&self, k: usize) -> bool {
+        let (i, j) = (k % self.nx, k / self.nx);
+        i == 0 || j == 0 || i == self.nx - 1 || j == self.ny - 1
+    }
+
+    /// Sample `(i, j)`, or `None` outside of the field.
+    pub fn get(&self, i: usize, j: usize) -> Option<f32> {
+        (i < self.nx && j < self.ny).then(|| self.data[j * self.nx + i])
+    }
+
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [f32] {
+        &mut self.data
+    }
+
+    /// Row `j`, i.e. samples `(0, j)..(nx, j)`.
+    pub fn row(&self, j: usize) -> &[f32] {
+        &self.data[j * self.nx..(j + 1) * self.nx]
+    }
+
+    /// Rows `(j, row)` that can be updated in parallel.
+    pub fn par_rows_mut(&mut self) -> impl IndexedParallelIterator<Item = (usize, &mut [f32])> {
+        self.data.par_chunks_mut(self.nx).enumerate()
+    }
+
+    pub fn fill(&mut self, value: f32) {
+        self.data.fill(value);
+    }
+
+    /// Copies the samples of `other`, which must have the same dimensions.
+    pub fn copy_from(&mut self, other: &Field2) {
+        self.data.copy_from_slice(&other.data);
+    }
+
+    /// Indices `(i, j)` of all samples but the outermost ring, in memory order.
+    pub fn interior(&self) -> impl Iterator<Item = (usize, usize)> {
+        let nx = self.nx;
+        (1..self.ny - 1).flat_map(move |j| (1..nx - 1).map(move |i| (i, j)))
+    }
+
+    /// Like [`Field2::interior`], but also yields the sample values.
+    pub fn interior_values(&self) -> impl Iterator<Item = ((usize, usize), f32)> + '_ {
+        self.interior().map(|(i, j)| ((i, j), self[(i, j)]))
+    }
+}
+
+impl Index<(usize, usize)> for Field2 {
+    type Output = f32;
+
+    #[inline]
+    fn index(&self, (i, j): (usize, usize)) -> &f32 {
+        &self.data[self.index(i, j)]
+    }
+}
+
+impl IndexMut<(usize, usize)> for Field2 {
+    #[inline]
+    fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut f32 {
+        let index = self.index(i, j);
+        &mut self.data[index]
+    }
+}
@@ -0,0 +1,231 @@
+//! FLIP/PIC velocity transport: particles carry the velocity through the grid, which only does
+//! the forces and the pressure projection.
+
+use macroquad::prelude::*;
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use crate::field::Field2;
+use crate::sampler::{Boundary, Interpolation, Sampler};
+use crate::scalar::jitter;
+
+/// Most particles a cell keeps, as a multiple of the number it is seeded with. FLIP particles
+/// bunch up where the flow converges, and without a limit reseeding the cells they leave would
+/// keep adding particles.
+const CROWDING: usize = 2;
+
+/// Settings of the particle velocity transport, see [`SimConfig::flip`](crate::SimConfig::flip).
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Flip {
+    /// Blend of the particle velocity update in `[0, 1]`: 0 is PIC, which takes the grid velocity
+    /// and is as dissipative as linear semi-Lagrangian advection, 1 is FLIP, which only adds the
+    /// change of the grid velocity and keeps small eddies but gets noisy.
+    pub ratio: f32,
+    /// Particles are seeded on a `per_axis` x `per_axis` jittered lattice in each cell.
+    pub per_axis: usize,
+}
+
+impl Default for Flip {
+    fn default() -> Flip {
+        Flip {
+            ratio: 0.95,
+            per_axis: 2,
+        }
+    }
+}
+
+/// Particles carrying the velocity of a FLIP/PIC simulation.
+#[derive(Clone, Debug)]
+pub struct Particles {
+    /// Positions in cell units, where the center of cell `(i, j)` is at `(i, j)`.
+    pub positions: Vec<Vec2>,
+    /// Velocities in m/s.
+    pub velocities: Vec<Vec2>,
+    /// The grid velocity right after the last transfer from the particles, which the change of
+    /// the grid velocity is measured against.
+    u: Field2,
+    v: Field2,
+}
+
+impl Particles {
+    /// No particles, for a grid with the dimensions of `u` and `v`.
+    pub fn new(u: &Field2, v: &Field2) -> Particles {
+        Particles {
+            positions: Vec::new(),
+            velocities: Vec::new(),
+            u: u.clone(),
+            v: v.clone(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Adds `per_axis` x `per_axis` particles to every interior cell `(i, j)` for which
+    /// `seed(i, j)` holds and that has no particles yet, moving with `velocity(position)`. Cells
+    /// holding more than `CROWDING` times that many drop the extra particles.
+    pub fn reseed(
+        &mut self,
+        per_axis: usize,
+        seed: impl Fn(usize, usize) -> bool,
+        velocity: impl Fn(Vec2) -> Vec2,
+    ) {
+        let (nx, ny) = (self.u.nx(), self.u.ny());
+        let limit = CROWDING * per_axis * per_axis;
+        let mut count = vec![0; nx * ny];
+        let keep: Vec<bool> = (self.positions.iter())
+            .map(|&x| {
+                let (i, j) = cell(x);
+                count[j * nx + i] += 1;
+                count[j * nx + i] <= limit
+            })
+            .collect();
+        let mut kept = keep.iter();
+        self.positions.retain(|_| *kept.next().unwrap());
+        let mut kept = keep.iter();
+        self.velocities.retain(|_| *kept.next().unwrap());
+
+        let spacing = 1.0 / per_axis as f32;
+        for j in 1..ny - 1 {
+            for i in 1..nx - 1 {
+                if count[j * nx + i] > 0 || !seed(i, j) {
+                    continue;
+                }
+                for sub in 0..per_axis * per_axis {
+                    let n = self.positions.len();
+                    let (a, b) = ((sub % per_axis) as f32, (sub / per_axis) as f32);
+                    let offset = vec2(a + jitter(2 * n), b + jitter(2 * n + 1)) * spacing;
+                    let x = vec2(i as f32, j as f32) - vec2(0.5, 0.5) + offset;
+                    self.positions.push(x);
+                    self.velocities.push(velocity(x));
+                }
+            }
+        }
+    }
+
+    /// Updates the particle velocities from the grid velocity `u`, `v` after the forces and the
+    /// projection: FLIP adds its change since [`Particles::to_grid`], PIC takes it as is, and
+    /// `ratio` blends the two.
+    pub fn from_grid(
+        &mut self,
+        u: &Field2,
+        v: &Field2,
+        s: &Field2,
+        boundary: Boundary,
+        ratio: f32,
+    ) {
+        let linear = |field| Sampler::new(field, s, boundary, Interpolation::Linear);
+        let (u_new, v_new) = (linear(u), linear(v));
+        let (u_old, v_old) = (linear(&self.u), linear(&self.v));
+        self.positions
+            .par_iter()
+            .zip(&mut self.velocities)
+            .for_each(|(&x, velocity)| {
+                let pic = vec2(u_new.at(x), v_new.at(x));
+                let flip = *velocity + pic - vec2(u_old.at(x), v_old.at(x));
+                *velocity = ratio * flip + (1.0 - ratio) * pic;
+            });
+    }
+
+    /// Moves the particles `step = dt / size` cells through the velocity `u`, `v` with the
+    /// midpoint rule, and drops those that end up outside of the cells where `keep(i, j)` holds.
+    pub fn advect(
+        &mut self,
+        u: &Field2,
+        v: &Field2,
+        s: &Field2,
+        boundary: Boundary,
+        step: f32,
+        keep: impl Fn(usize, usize) -> bool,
+    ) {
+        let u = Sampler::new(u, s, boundary, Interpolation::Linear);
+        let v = Sampler::new(v, s, boundary, Interpolation::Linear);
+        let velocity = |x: Vec2| vec2(u.at(x), v.at(x));
+        self.positions.par_iter_mut().for_each(|x| {
+            let mid = *x + velocity(*x) * step / 2.0;
+            *x += velocity(mid) * step;
+        });
+
+        let (nx, ny) = (s.nx(), s.ny());
+        let inside = |x: Vec2| {
+            let (i, j) = cell(x);
+            x.x >= 0.0 && x.y >= 0.0 && i < nx && j < ny && keep(i, j)
+        };
+        let mut k = 0;
+        while k < self.positions.len() {
+            if inside(self.positions[k]) {
+                k += 1;
+            } else {
+                self.positions.swap_remove(k);
+                self.velocities.swap_remove(k);
+            }
+        }
+    }
+
+    /// Splats the particle velocities onto the faces of `u` and `v` for which `open_u(i, j)` and
+    /// `open_v(i, j)` hold, weighted bilinearly, and remembers the result for
+    /// [`Particles::from_grid`]. Faces without particles nearby keep their velocity.
+    pub fn to_grid(
+        &mut self,
+        u: &mut Field2,
+        v: &mut Field2,
+        open_u: impl Fn(usize, usize) -> bool,
+        open_v: impl Fn(usize, usize) -> bool,
+    ) {
+        splat(u, &self.positions, |k| self.velocities[k].x, open_u);
+        splat(v, &self.positions, |k| self.velocities[k].y, open_v);
+        self.u.copy_from(u);
+        self.v.copy_from(v);
+    }
+}
+
+/// Sets every `open` sample of `field` to the weighted average of `value(k)` over the particles
+/// at `positions` within one sample spacing.
+fn splat(
+    field: &mut Field2,
+    positions: &[Vec2],
+    value: impl Fn(usize) -> f32,
+    open: impl Fn(usize, usize) -> bool,
+) {
+    let (nx, ny) = (field.nx(), field.ny());
+    let mut sum = vec![0.0; nx * ny];
+    let mut weight = vec![0.0; nx * ny];
+    for (k, &x) in positions.iter().enumerate() {
+        let q = x - field.stagger().offset();
+        let (i, j) = (q.x.floor() as usize, q.y.floor() as usize);
+        let (fx, fy) = (q.x - i as f32, q.y - j as f32);
+        let corners = [
+            (i, j, (1.0 - fx) * (1.0 - fy)),
+            (i + 1, j, fx * (1.0 - fy)),
+            (i, j + 1, (1.0 - fx) * fy),
+            (i + 1, j + 1, fx * fy),
+        ];
+        for (i, j, w) in corners {
+            if i < nx && j < ny {
+                sum[j * nx + i] += w * value(k);
+                weight[j * nx + i] += w;
+            }
+        }
+    }
+
+    for j in 0..ny {
+        for i in 0..nx {
+            let n = j * nx + i;
+            if weight[n] > 0.0 && open(i, j) {
+                field[(i, j)] = sum[n] / weight[n];
+            }
+        }
+    }
+}
+
+/// Cell containing position `x` in cell units.
+fn cell(x: Vec2) -> (usize, usize) {
+    let x = (x + vec2(0.5, 0.5)).max(Vec2::ZERO);
+    (x.x as usize, x.y as usize)
+}
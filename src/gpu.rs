@@ -1,6 +1,20 @@
+use bytemuck::{Pod, Zeroable};
+use macroquad::math::vec2;
 use wgpu::*;
 use winit::window::Window;
 
+use crate::boundary::{self, Boundaries, BoundaryCondition, Inflow, Variation};
+use crate::config::Side;
+use crate::field::Stagger;
+use crate::mask::{Marking, MaskFace};
+use crate::scene::Scene;
+use crate::timestep::{self, Substeps};
+
+/// Cells along each side of a workgroup, see `@workgroup_size` in `compute.wgsl`.
+const WORKGROUP_SIZE: u32 = 16;
+/// Faces per workgroup of the `enforce_boundaries` pass.
+const BORDER_WORKGROUP_SIZE: u32 = 64;
+
 pub struct SharedState {
     device: Device,
     queue: Queue,
@@ -56,8 +70,197 @@ impl SharedState {
     }
 }
 
+/// One grid cell of the physics storage buffer, mirrors `Point` in `compute.wgsl`.
+/// Cells are stored row-major like [`Field2`](crate::Field2), cell `(i, j)` at `j * row_size + i`.
+#[derive(Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+struct Point {
+    u: f32,
+    v: f32,
+    s: f32,
+    rho: f32,
+    avg_div: f32,
+    nu: f32,
+    nv: f32,
+    nrho: f32,
+}
+
+/// Per-step parameters, mirrors `Params` in `compute.wgsl`.
+#[derive(Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+struct Params {
+    dt: f32,
+    cell_size: f32,
+    gravity: f32,
+    vorticity_confinement: f32,
+    /// Cells along x and y, walls included.
+    size: [u32; 2],
+    border_faces: u32,
+    /// Aligns `factors` to 16 bytes.
+    padding: u32,
+    /// Fraction of the full velocity of the [`BorderFace`]s of each side, in the order of
+    /// [`Boundaries::iter`].
+    factors: [f32; 4],
+}
+
+/// A face across a side of the domain that the `enforce_boundaries` pass sets at the start of
+/// every step, mirrors `BorderFace` in `compute.wgsl`.
+#[derive(Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+struct BorderFace {
+    index: u32,
+    /// The parallel face on the other side of the cell inside.
+    inner: u32,
+    /// Position of the side in [`Boundaries::iter`].
+    side: u32,
+    /// 1 for a `u` face, 0 for a `v` face.
+    across_u: u32,
+    /// 1 to copy the velocity of `inner` as long as it points out of the domain.
+    zero_gradient: u32,
+    /// Velocity at full speed in m/s, or the outward direction for a zero gradient.
+    velocity: f32,
+}
+
+/// What the `enforce_boundaries` pass needs to know about the sides of the domain.
+struct Sides {
+    boundaries: Boundaries,
+    faces: Vec<BorderFace>,
+    /// Flux of each inflow side at full speed, in m/s summed over its faces.
+    inflow: [f32; 4],
+    /// Net flux into the fluid through the faces of the inflow and outflow cells of the mask, in
+    /// m/s summed over the faces.
+    mask: f32,
+    /// Whether the outlets carry away exactly what the inflows bring in, which open sides take
+    /// over otherwise.
+    balanced: bool,
+}
+
+impl Sides {
+    /// The faces across the sides of `scene` whose cell inside is fluid in `values`: the inflows
+    /// with their profile, and a uniform outward velocity through the outflow and convective
+    /// sides that carries away what the inflows and the mask bring in. With an open side these copy the
+    /// velocity next to them instead, and the open sides themselves are left free.
+    fn new(scene: &Scene, values: &[Point]) -> Sides {
+        let boundaries = scene.config.boundaries;
+        let (width, height, row) = (scene.width, scene.height, scene.width + 2);
+        let balanced = !boundaries.iter().any(|(_, c)| c == BoundaryCondition::Open);
+        let mut faces = Vec::new();
+        let mut inflow = [0.0; 4];
+        let mut outlets = Vec::new();
+        for (n, (side, condition)) in boundaries.iter().enumerate() {
+            let outward = side.outward();
+            for face in boundary::side_faces(side, width, height) {
+                if values[face.cell.0 + face.cell.1 * row].s == 0.0 {
+                    continue;
+                }
+                let mut border = BorderFace {
+                    index: (face.face.0 + face.face.1 * row) as u32,
+                    inner: (face.inner.0 + face.inner.1 * row) as u32,
+                    side: n as u32,
+                    across_u: matches!(side, Side::Left | Side::Right) as u32,
+                    zero_gradient: 0,
+                    velocity: outward,
+                };
+                match condition {
+                    BoundaryCondition::Wall | BoundaryCondition::Open => continue,
+                    BoundaryCondition::Inflow(inflow_condition) => {
+                        let steady = Inflow {
+                            variation: Variation::Steady,
+                            ..inflow_condition
+                        };
+                        let speed = steady.speed_at(face.x, 0.0);
+                        border.velocity = -outward * speed;
+                        inflow[n] += speed;
+                    }
+                    BoundaryCondition::Outflow | BoundaryCondition::Convective { .. } => {
+                        if balanced {
+                            outlets.push(faces.len());
+                        } else {
+                            border.zero_gradient = 1;
+                        }
+                    }
+                }
+                faces.push(border);
+            }
+        }
+        let mask = scene.config.mask.as_ref().map_or(0.0, |mask| {
+            let faces = mask.faces(width, height, scene.config.mask_speed, |i, j| {
+                values[i + j * row].s != 0.0
+            });
+            faces.iter().map(MaskFace::inflow).sum()
+        });
+        let flux_in = inflow.iter().sum::<f32>() + mask;
+        let speed_out = flux_in.max(0.0) / outlets.len().max(1) as f32;
+        for index in outlets {
+            faces[index].velocity *= speed_out;
+        }
+        Sides {
+            boundaries,
+            faces,
+            inflow,
+            mask,
+            balanced,
+        }
+    }
+
+    /// Fraction of the full velocity of each side at time `t` in seconds: the variation of the
+    /// inflows, and the outlets following the total inflow with the steady one of the mask.
+    fn factors(&self, t: f32) -> [f32; 4] {
+        let mut factors = [0.0; 4];
+        let mut flux_in = self.mask;
+        for (n, (_, condition)) in self.boundaries.iter().enumerate() {
+            if let BoundaryCondition::Inflow(inflow) = condition {
+                factors[n] = inflow.variation.factor(t);
+                flux_in += self.inflow[n] * factors[n];
+            }
+        }
+        let full = self.inflow.iter().sum::<f32>() + self.mask;
+        for (n, (_, condition)) in self.boundaries.iter().enumerate() {
+            if self.balanced && condition.is_outlet() {
+                factors[n] = if full > 0.0 {
+                    flux_in.max(0.0) / full
+                } else {
+                    0.0
+                };
+            }
+        }
+        factors
+    }
+
+    /// Sets the faces in `values` like the `enforce_boundaries` pass does at time `t`.
+    fn apply(&self, values: &mut [Point], t: f32) {
+        let factors = self.factors(t);
+        for face in &self.faces {
+            let value = |point: &Point| if face.across_u == 1 { point.u } else { point.v };
+            let velocity = if face.zero_gradient == 1 {
+                let inner = value(&values[face.inner as usize]);
+                face.velocity * (face.velocity * inner).max(0.0)
+            } else {
+                face.velocity * factors[face.side as usize]
+            };
+            let point = &mut values[face.index as usize];
+            *if face.across_u == 1 {
+                &mut point.u
+            } else {
+                &mut point.v
+            } = velocity;
+        }
+    }
+}
+
+/// The simulation on the GPU, a subset of the CPU [`Grid`](crate::Grid) with a grid sized to the
+/// scene. It takes the obstacles where they start, the mask, the boundaries, the initial
+/// velocity, gravity, the cell size, the CFL number and vorticity confinement from the scene, and
+/// projects the pressure with `scene.gpu_iterations` Jacobi passes.
+///
+/// It does not move the obstacles, and has no scalars, buoyancy, viscosity, liquid, particles or
+/// tracers. Convective sides act like outflows.
 pub struct ComputeState {
+    enforce_boundaries_pipeline: ComputePipeline,
     integrate_pipeline: ComputePipeline,
+    curl_pipeline: ComputePipeline,
+    confinement_force_pipeline: ComputePipeline,
+    apply_confinement_pipeline: ComputePipeline,
     gather_pipeline: ComputePipeline,
     scatter_bl_pipeline: ComputePipeline,
     scatter_tr_pipeline: ComputePipeline,
@@ -65,14 +268,29 @@ pub struct ComputeState {
     advect_v_pipeline: ComputePipeline,
     advect_density_pipeline: ComputePipeline,
     copy_pipeline: ComputePipeline,
+    measure_speed_pipeline: ComputePipeline,
     physics_bind_group: BindGroup,
     compute_output_bind_group: BindGroup,
     storage_buffer: Buffer,
+    params_buffer: Buffer,
+    speed_buffer: Buffer,
+    speed_readback_buffer: Buffer,
     image_view: TextureView,
+    /// Cells along x and y, walls included.
+    size: (usize, usize),
+    sides: Sides,
+    /// Simulated time in seconds.
+    time: f32,
+    pressure_iterations: usize,
+    cfl: f32,
+    vorticity_confinement: f32,
+    /// Cell size in meters.
+    cell_size: f32,
+    gravity: f32,
 }
 
 impl ComputeState {
-    pub fn new(shared: &SharedState) -> ComputeState {
+    pub fn new(shared: &SharedState, scene: &Scene) -> ComputeState {
         let device = &shared.device;
         let source = ShaderSource::Wgsl(include_str!("compute.wgsl").into());
         let shader_module = device.create_shader_module(ShaderModuleDescriptor {
@@ -80,20 +298,26 @@ impl ComputeState {
             source,
         });
 
-        let entry = BindGroupLayoutEntry {
-            binding: 0,
+        let buffer_entry = |binding, ty| BindGroupLayoutEntry {
+            binding,
             visibility: ShaderStages::COMPUTE,
             ty: BindingType::Buffer {
-                ty: BufferBindingType::Storage { read_only: false },
+                ty,
                 has_dynamic_offset: false,
                 min_binding_size: None,
             },
             count: None,
         };
+        let storage = BufferBindingType::Storage { read_only: false };
         let physics_bind_group_layout =
             device.create_bind_group_layout(&BindGroupLayoutDescriptor {
                 label: Some("Physics bind group layout"),
-                entries: &[entry],
+                entries: &[
+                    buffer_entry(0, storage),
+                    buffer_entry(1, BufferBindingType::Uniform),
+                    buffer_entry(2, storage),
+                    buffer_entry(3, BufferBindingType::Storage { read_only: true }),
+                ],
             });
         let physics_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("Physics pipeline layout"),
@@ -101,12 +325,39 @@ impl ComputeState {
             push_constant_ranges: &[],
         });
 
+        let enforce_boundaries_pipeline =
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some("Physics enforce boundaries pipeline"),
+                layout: Some(&physics_pipeline_layout),
+                module: &shader_module,
+                entry_point: "enforce_boundaries",
+            });
         let integrate_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
             label: Some("Physics integrate pipeline"),
             layout: Some(&physics_pipeline_layout),
             module: &shader_module,
             entry_point: "integrate",
         });
+        let curl_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Physics curl pipeline"),
+            layout: Some(&physics_pipeline_layout),
+            module: &shader_module,
+            entry_point: "curl",
+        });
+        let confinement_force_pipeline =
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some("Physics confinement force pipeline"),
+                layout: Some(&physics_pipeline_layout),
+                module: &shader_module,
+                entry_point: "confinement_force",
+            });
+        let apply_confinement_pipeline =
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some("Physics apply confinement pipeline"),
+                layout: Some(&physics_pipeline_layout),
+                module: &shader_module,
+                entry_point: "apply_confinement",
+            });
         let gather_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
             label: Some("Physics gather pipeline"),
             layout: Some(&physics_pipeline_layout),
@@ -143,6 +394,12 @@ impl ComputeState {
             module: &shader_module,
             entry_point: "advect_density",
         });
+        let measure_speed_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Physics measure speed pipeline"),
+            layout: Some(&physics_pipeline_layout),
+            module: &shader_module,
+            entry_point: "measure_speed",
+        });
 
         let entry = BindGroupLayoutEntry {
             binding: 0,
@@ -174,53 +431,10 @@ impl ComputeState {
             entry_point: "copy",
         });
 
-        use bytemuck::Zeroable;
-        use bytemuck::Pod;
-        #[derive(Clone, Copy, Zeroable, Pod)]
-        #[repr(C)]
-        struct Point {
-            u: f32,
-            v: f32,
-            s: f32,
-            rho: f32,
-            avg_div: f32,
-            nu: f32,
-            nv: f32,
-            nrho: f32,
-        }
-
-        const SIZE: (usize, usize) = (256, 256);
-        let init = Point{u: 0.0, v: 0.0, s: 1.0, rho: 0.0, avg_div: 0.0, nu: 0.0, nv: 0.0, nrho: 0.0};
-        let mut values = vec![init; SIZE.0 * SIZE.1];
-
-        // Walls
-        for i in 0..SIZE.0 {
-            values[i].s = 0.0;
-            values[(SIZE.1 - 1) * SIZE.0 + i].s = 0.0;
-        }
-        for j in 0..SIZE.1 {
-            values[j * SIZE.0].s = 0.0;
-            values[(j + 1) * SIZE.0 - 1].s = 0.0;
-        }
-
-        let radius: i32 = 20;
-        let pos = (50, 128);
-        // Obstacle
-        for i in 0..SIZE.0 {
-            for j in 0..SIZE.1 {
-                let sq_dist = (i as i32 - pos.0).pow(2) + (j as i32 - pos.1).pow(2);
-                if sq_dist <= radius.pow(2) {
-                    let index = i as usize + j as usize * SIZE.0;
-                    values[index].s = 0.0;
-                    values[index].rho = 1.0;
-                }
-            }
-        }
-
-        // Inflow from the center left
-        values[256 * 128 + 1].u = 100.0;
-        // Outflow into center right
-        values[256 * 128 + 255].u = 100.0;
+        let size = (scene.width + 2, scene.height + 2);
+        let mut values = initial_points(scene);
+        let sides = Sides::new(scene, &values);
+        sides.apply(&mut values, 0.0);
 
         let storage_buffer = {
             use util::DeviceExt;
@@ -230,20 +444,65 @@ impl ComputeState {
                 usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
             })
         };
+        // At least one face, so the binding is not empty
+        let border_faces_buffer = {
+            use util::DeviceExt;
+            let mut faces = sides.faces.clone();
+            if faces.is_empty() {
+                faces.push(BorderFace::zeroed());
+            }
+            device.create_buffer_init(&util::BufferInitDescriptor {
+                label: Some("Physics border faces buffer"),
+                contents: bytemuck::cast_slice(&faces[..]),
+                usage: BufferUsages::STORAGE,
+            })
+        };
+        let params_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Physics params buffer"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let speed_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Physics max speed buffer"),
+            size: 4,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let speed_readback_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Physics max speed readback buffer"),
+            size: 4,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
         let physics_bind_group = device.create_bind_group(&BindGroupDescriptor {
             label: Some("Physics bind group"),
             layout: &physics_bind_group_layout,
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: storage_buffer.as_entire_binding(),
-            }],
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: storage_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: speed_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: border_faces_buffer.as_entire_binding(),
+                },
+            ],
         });
 
         let img = device.create_texture(&TextureDescriptor {
             label: Some("Copy output texture"),
             size: Extent3d {
-                width: SIZE.0 as u32,
-                height: SIZE.1 as u32,
+                width: size.0 as u32,
+                height: size.1 as u32,
                 depth_or_array_layers: 1,
             },
             mip_level_count: 1,
@@ -263,7 +522,11 @@ impl ComputeState {
         });
 
         ComputeState {
+            enforce_boundaries_pipeline,
             integrate_pipeline,
+            curl_pipeline,
+            confinement_force_pipeline,
+            apply_confinement_pipeline,
             gather_pipeline,
             scatter_bl_pipeline,
             scatter_tr_pipeline,
@@ -271,14 +534,102 @@ impl ComputeState {
             advect_v_pipeline,
             advect_density_pipeline,
             copy_pipeline,
+            measure_speed_pipeline,
             physics_bind_group,
             compute_output_bind_group,
             storage_buffer,
+            params_buffer,
+            speed_buffer,
+            speed_readback_buffer,
             image_view: img_view,
+            size,
+            sides,
+            time: 0.0,
+            pressure_iterations: scene.gpu_iterations,
+            cfl: scene.config.cfl,
+            vorticity_confinement: scene.config.vorticity_confinement,
+            cell_size: scene.cell_size,
+            gravity: scene.config.gravity,
         }
     }
 
-    pub fn run(&self, shared: &SharedState) {
+    /// Sets the number of Jacobi gather and scatter passes of the pressure projection per step.
+    pub fn set_pressure_iterations(&mut self, iterations: usize) {
+        self.pressure_iterations = iterations;
+    }
+
+    /// Sets the largest distance in cells the fluid may move per substep of
+    /// [`ComputeState::advance`].
+    pub fn set_cfl(&mut self, cfl: f32) {
+        self.cfl = cfl;
+    }
+
+    /// Sets the strength of the vorticity confinement force, 0 to disable it.
+    pub fn set_vorticity_confinement(&mut self, epsilon: f32) {
+        self.vorticity_confinement = epsilon;
+    }
+
+    /// Advances the simulation by `frame_time` seconds in substeps limited by the CFL condition.
+    pub fn advance(&mut self, shared: &SharedState, frame_time: f32) -> Substeps {
+        let max_dt = |compute: &ComputeState| {
+            let max_speed = compute.max_velocity(shared);
+            timestep::cfl_dt(max_speed, compute.cell_size, compute.cfl)
+        };
+        timestep::advance(self, frame_time, max_dt, |compute, dt| {
+            compute.run(shared, dt)
+        })
+    }
+
+    /// Largest speed on any face in m/s, read back from the GPU.
+    pub fn max_velocity(&self, shared: &SharedState) -> f32 {
+        let mut encoder = shared
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Measure speed command encoder"),
+            });
+        encoder.clear_buffer(&self.speed_buffer, 0, None);
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Measure speed compute pass"),
+        });
+        let (x, y) = self.workgroups();
+        pass.set_pipeline(&self.measure_speed_pipeline);
+        pass.set_bind_group(0, &self.physics_bind_group, &[]);
+        pass.dispatch_workgroups(x, y, 1);
+        drop(pass);
+
+        encoder.copy_buffer_to_buffer(&self.speed_buffer, 0, &self.speed_readback_buffer, 0, 4);
+        shared.queue.submit(Some(encoder.finish()));
+
+        let slice = self.speed_readback_buffer.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(MapMode::Read, move |_| {
+            tx.send(()).expect("Receiver lives");
+        });
+        shared.device.poll(MaintainBase::Wait);
+        rx.recv().expect("Sender lives");
+        let max_speed = *bytemuck::from_bytes::<f32>(&slice.get_mapped_range());
+        self.speed_readback_buffer.unmap();
+        max_speed
+    }
+
+    /// Advances the simulation by `dt` seconds.
+    pub fn run(&mut self, shared: &SharedState, dt: f32) {
+        self.time += dt;
+        let params = Params {
+            dt,
+            cell_size: self.cell_size,
+            gravity: self.gravity,
+            vorticity_confinement: self.vorticity_confinement,
+            size: [self.size.0 as u32, self.size.1 as u32],
+            border_faces: self.sides.faces.len() as u32,
+            padding: 0,
+            factors: self.sides.factors(self.time),
+        };
+        shared
+            .queue
+            .write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&params));
+
         // Create passes, copy between buffers and textures
         let mut encoder = shared
             .device
@@ -290,49 +641,68 @@ impl ComputeState {
             label: Some("Compute compute pass"),
         });
 
-        // Each axis: grid size = 256, workgroup_size = 16, num groups = 16
-        let groups = 256 / 16;
+        let (x, y) = self.workgroups();
+
+        let border_groups = (self.sides.faces.len() as u32).div_ceil(BORDER_WORKGROUP_SIZE);
+        if border_groups > 0 {
+            pass.set_pipeline(&self.enforce_boundaries_pipeline);
+            pass.set_bind_group(0, &self.physics_bind_group, &[]);
+            pass.dispatch_workgroups(border_groups, 1, 1);
+        }
 
         pass.set_pipeline(&self.integrate_pipeline);
         pass.set_bind_group(0, &self.physics_bind_group, &[]);
-        pass.dispatch_workgroups(groups, groups, 1);
+        pass.dispatch_workgroups(x, y, 1);
+
+        if self.vorticity_confinement > 0.0 {
+            for pipeline in [
+                &self.curl_pipeline,
+                &self.confinement_force_pipeline,
+                &self.apply_confinement_pipeline,
+            ] {
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, &self.physics_bind_group, &[]);
+                pass.dispatch_workgroups(x, y, 1);
+            }
+        }
 
-        for _ in 0..1000 {
+        for _ in 0..self.pressure_iterations {
             pass.set_pipeline(&self.gather_pipeline);
             pass.set_bind_group(0, &self.physics_bind_group, &[]);
-            pass.dispatch_workgroups(groups, groups, 1);
+            pass.dispatch_workgroups(x, y, 1);
 
             pass.set_pipeline(&self.scatter_bl_pipeline);
             pass.set_bind_group(0, &self.physics_bind_group, &[]);
-            pass.dispatch_workgroups(groups, groups, 1);
+            pass.dispatch_workgroups(x, y, 1);
 
             pass.set_pipeline(&self.scatter_tr_pipeline);
             pass.set_bind_group(0, &self.physics_bind_group, &[]);
-            pass.dispatch_workgroups(groups, groups, 1);
+            pass.dispatch_workgroups(x, y, 1);
         }
 
         pass.set_pipeline(&self.advect_u_pipeline);
         pass.set_bind_group(0, &self.physics_bind_group, &[]);
-        pass.dispatch_workgroups(groups, groups, 1);
+        pass.dispatch_workgroups(x, y, 1);
 
         pass.set_pipeline(&self.advect_v_pipeline);
         pass.set_bind_group(0, &self.physics_bind_group, &[]);
-        pass.dispatch_workgroups(groups, groups, 1);
+        pass.dispatch_workgroups(x, y, 1);
 
         pass.set_pipeline(&self.advect_density_pipeline);
         pass.set_bind_group(0, &self.physics_bind_group, &[]);
-        pass.dispatch_workgroups(groups, groups, 1);
+        pass.dispatch_workgroups(x, y, 1);
 
         pass.set_pipeline(&self.copy_pipeline);
         pass.set_bind_group(0, &self.physics_bind_group, &[]);
         pass.set_bind_group(1, &self.compute_output_bind_group, &[]);
-        pass.dispatch_workgroups(groups, groups, 1);
+        pass.dispatch_workgroups(x, y, 1);
 
         drop(pass);
         shared.queue.submit(Some(encoder.finish()));
     }
 
-    fn inspect(&self, shared: &SharedState) {
+    /// Reads the first row of the storage buffer back to the CPU and prints it.
+    pub fn inspect(&self, shared: &SharedState) {
         let mut encoder = shared
             .device
             .create_command_encoder(&CommandEncoderDescriptor {
@@ -341,7 +711,7 @@ impl ComputeState {
         let size = self.storage_buffer.size();
         let output_buffer = shared.device.create_buffer(&BufferDescriptor {
             label: Some("Compute output buffer"),
-            size: size,
+            size,
             usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -356,9 +726,18 @@ impl ComputeState {
         shared.device.poll(MaintainBase::Wait);
         rx.recv().expect("Sender lives");
         let view = output_buffer.slice(..).get_mapped_range();
-        let size = 256 * 4;
-        let chunk = view.chunks(size).next().unwrap();
-        println!("{:?}", bytemuck::from_bytes::<[f32; 256]>(chunk));
+        let points: &[Point] = bytemuck::cast_slice(&view);
+        let row: Vec<[f32; 8]> = points[..self.size.0]
+            .iter()
+            .map(|p| bytemuck::cast(*p))
+            .collect();
+        println!("{row:?}");
+    }
+
+    /// Workgroups along x and y that cover the grid.
+    fn workgroups(&self) -> (u32, u32) {
+        let groups = |cells: usize| (cells as u32).div_ceil(WORKGROUP_SIZE);
+        (groups(self.size.0), groups(self.size.1))
     }
 }
 
@@ -493,3 +872,116 @@ impl RenderState {
         surface_texture.present();
     }
 }
+
+/// Initial state of every cell for `scene`, row by row with the walls around it: the obstacles
+/// where they start, the mask and the initial velocity. The velocity across the sides is left to
+/// [`Sides::apply`].
+fn initial_points(scene: &Scene) -> Vec<Point> {
+    let (width, height) = (scene.width, scene.height);
+    let row = width + 2;
+    let init = Point {
+        u: 0.0,
+        v: 0.0,
+        s: 1.0,
+        rho: 0.0,
+        avg_div: 0.0,
+        nu: 0.0,
+        nv: 0.0,
+        nrho: 0.0,
+    };
+    let mut values = vec![init; row * (height + 2)];
+
+    // Walls
+    for i in 0..row {
+        values[i].s = 0.0;
+        values[(height + 1) * row + i].s = 0.0;
+    }
+    for j in 0..height + 2 {
+        values[j * row].s = 0.0;
+        values[(j + 1) * row - 1].s = 0.0;
+    }
+    // Open sides are fluid beyond the border, where the pressure stays at zero
+    for (side, condition) in scene.config.boundaries.iter() {
+        if condition == BoundaryCondition::Open {
+            for face in boundary::side_faces(side, width, height) {
+                values[face.outside.0 + face.outside.1 * row].s = 1.0;
+            }
+        }
+    }
+
+    let config = &scene.config;
+    let marking = |i, j| {
+        config
+            .mask
+            .as_ref()
+            .map_or(Marking::Fluid, |mask| mask.at(i, j, width, height))
+    };
+    // Obstacles and the mask
+    for j in 1..=height {
+        for i in 1..=width {
+            let x = vec2(i as f32, j as f32);
+            let in_disk = config.obstacle.is_some_and(|disk| {
+                let (center, radius) = disk.in_cells(width, height);
+                x.distance_squared(center) < radius * radius
+            });
+            let in_obstacle = config
+                .obstacles
+                .iter()
+                .any(|o| o.contains(x, width, height));
+            let point = &mut values[i + j * row];
+            match marking(i, j) {
+                _ if in_disk || in_obstacle => {
+                    point.s = 0.0;
+                    point.rho = 1.0;
+                }
+                Marking::Fluid => {}
+                Marking::Solid => {
+                    point.s = 0.0;
+                    point.rho = 1.0;
+                }
+                Marking::Inflow | Marking::Outflow => point.s = 0.0,
+                Marking::Dye => point.rho = 1.0,
+            }
+        }
+    }
+
+    // Faces from inflow cells into the fluid and from the fluid into outflow cells
+    if let Some(mask) = &config.mask {
+        let faces = mask.faces(width, height, config.mask_speed, |i, j| {
+            values[i + j * row].s != 0.0
+        });
+        for face in faces {
+            let point = &mut values[face.face.0 + face.face.1 * row];
+            match face.stagger {
+                Stagger::XFace => point.u = face.velocity,
+                _ => point.v = face.velocity,
+            }
+        }
+    }
+
+    // Initial velocity, on the faces of the fluid cells in each region
+    for initial in &config.initial_velocity {
+        let inside = |i, j| {
+            let mask = config.mask.as_ref();
+            initial.region.contains(i, j, width, height, mask)
+        };
+        for j in 1..=height {
+            for i in 1..=width {
+                let index = i + j * row;
+                let fluid = |index: usize| values[index].s != 0.0;
+                let across_u = fluid(index - 1) && (inside(i, j) || inside(i - 1, j));
+                let across_v = fluid(index - row) && (inside(i, j) || inside(i, j - 1));
+                if !fluid(index) {
+                    continue;
+                }
+                if across_u {
+                    values[index].u = initial.velocity.x;
+                }
+                if across_v {
+                    values[index].v = initial.velocity.y;
+                }
+            }
+        }
+    }
+    values
+}
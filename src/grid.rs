@@ -0,0 +1,944 @@
+use std::sync::Arc;
+
+use macroquad::prelude::*;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::advect::{self, Path};
+use crate::boundary::{self, BoundaryCondition};
+use crate::config::{self, ConfigError, Side, SimConfig};
+use crate::diffusion::{self, Condition};
+use crate::field::{Field2, Stagger};
+use crate::flip::Particles;
+use crate::level_set;
+use crate::mask::{Marking, MaskFace};
+use crate::obstacle::Obstacle;
+use crate::pressure::{PressureSolver, Projection, SolveReport};
+use crate::sampler::Sampler;
+use crate::scalar;
+use crate::timestep::{self, StepReport};
+use crate::tracer::Tracer;
+
+/// A MAC grid with `width` x `height` fluid cells of side `size` surrounded by a
+/// one-cell border. Every field has `(width + 2) x (height + 2)` samples indexed `field[(i, j)]`.
+pub struct Grid {
+    width: usize,
+    height: usize,
+    size: f32,
+    u: Field2,
+    v: Field2,
+    s: Field2,
+    /// Solid mask of the walls and static obstacles, which the moving obstacles are painted over.
+    fixed: Field2,
+    rho: Field2,
+    p: Field2,
+    /// Passive scalars in the order of `config.scalars`.
+    scalars: Vec<Field2>,
+    /// Level set of the free surface in meters, negative in the liquid.
+    phi: Option<Field2>,
+    /// Particles carrying the velocity in FLIP/PIC mode.
+    particles: Option<Particles>,
+    tracers: Vec<Tracer>,
+    /// The moving obstacles of `config.obstacles` where they are now.
+    obstacles: Vec<Obstacle>,
+    /// Faces of the inflow and outflow cells of `config.mask`.
+    mask_faces: Vec<MaskFace>,
+    /// Simulated time in seconds.
+    time: f32,
+    config: SimConfig,
+    solver: Box<dyn PressureSolver>,
+    pool: Arc<ThreadPool>,
+}
+
+impl Grid {
+    /// A domain set up according to `config`, with the conditions of
+    /// [`SimConfig::boundaries`] on its sides.
+    pub fn new(
+        width: usize,
+        height: usize,
+        size: f32,
+        config: SimConfig,
+    ) -> Result<Grid, ConfigError> {
+        config::validate_grid(width, height, size)?;
+        config.validate()?;
+
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(config.threads)
+            .build()
+            .map_err(|err| ConfigError::ThreadPool(err.to_string()))?;
+
+        let obstacle = config.obstacle;
+        let mut grid = Grid::empty(width, height, size, config, Arc::new(pool));
+
+        if let Some(disk) = obstacle {
+            let (pos, radius) = disk.in_cells(width, height);
+            grid.add_disk_obstacle(pos, radius);
+        }
+        grid.apply_mask();
+        grid.enforce_boundaries(0.0);
+        grid.obstacles = grid.config.obstacles.clone();
+        grid.rasterize_obstacles();
+        grid.apply_initial_fields();
+
+        if !grid.config.liquid.is_empty() {
+            let mut phi = Field2::new(width + 2, height + 2, Stagger::Center, 0.0);
+            level_set::initialize(&mut phi, &grid.config.liquid, size);
+            grid.phi = Some(phi);
+        }
+        let mut tracers = Vec::new();
+        grid.config
+            .tracers
+            .release(width, height, true, &mut tracers);
+        grid.tracers = tracers;
+        if grid.config.flip.is_some() {
+            grid.particles = Some(Particles::new(&grid.u, &grid.v));
+            grid.transfer_to_grid();
+        }
+
+        Ok(grid)
+    }
+
+    /// A grid at rest, enclosed by walls on all four sides but the open ones.
+    fn empty(
+        width: usize,
+        height: usize,
+        size: f32,
+        config: SimConfig,
+        pool: Arc<ThreadPool>,
+    ) -> Grid {
+        let (nx, ny) = (width + 2, height + 2);
+
+        let u = Field2::new(nx, ny, Stagger::XFace, 0.0);
+        let v = Field2::new(nx, ny, Stagger::YFace, 0.0);
+        let mut s = Field2::new(nx, ny, Stagger::Center, 1.0);
+        let rho = Field2::new(nx, ny, Stagger::Center, 0.0);
+        let p = Field2::new(nx, ny, Stagger::Center, 0.0);
+        let scalars = vec![Field2::new(nx, ny, Stagger::Center, 0.0); config.scalars.len()];
+
+        // vertical walls
+        for j in 0..ny {
+            s[(0, j)] = 0.0;
+            s[(width + 1, j)] = 0.0;
+        }
+
+        // horizontal walls
+        for i in 0..nx {
+            s[(i, 0)] = 0.0;
+            s[(i, height + 1)] = 0.0;
+        }
+
+        // Open sides are fluid beyond the border, where the projection keeps the pressure at zero
+        for (side, condition) in config.boundaries.iter() {
+            if condition == BoundaryCondition::Open {
+                for face in boundary::side_faces(side, width, height) {
+                    s[face.outside] = 1.0;
+                }
+            }
+        }
+
+        Grid {
+            width,
+            height,
+            size,
+            u,
+            v,
+            fixed: s.clone(),
+            s,   // 1.0 fluid, 0.0 solid
+            rho, // density
+            p,   // pressure
+            scalars,
+            phi: None,
+            particles: None,
+            tracers: Vec::new(),
+            obstacles: Vec::new(),
+            mask_faces: Vec::new(),
+            time: 0.0,
+            solver: config.solver.build(&config),
+            config,
+            pool,
+        }
+    }
+
+    /// Marks the cells within `radius` of `pos` (in cell units) as solid and fills them with density.
+    pub fn add_disk_obstacle(&mut self, pos: Vec2, radius: f32) {
+        for j in 1..=self.height {
+            for i in 1..=self.width {
+                if vec2(i as f32, j as f32).distance_squared(pos) < radius * radius {
+                    self.s[(i, j)] = 0.0;
+                    self.fixed[(i, j)] = 0.0;
+                    self.rho[(i, j)] = 1.0;
+                }
+            }
+        }
+    }
+
+    /// Paints the solid, inflow and outflow cells of [`SimConfig::mask`] into the solid mask, and
+    /// collects the faces between them and the fluid, which [`Grid::enforce_boundaries`] holds at
+    /// [`SimConfig::mask_speed`].
+    fn apply_mask(&mut self) {
+        let Some(mask) = &self.config.mask else {
+            return;
+        };
+        let (width, height) = (self.width, self.height);
+        let marking = |i, j| mask.at(i, j, width, height);
+        for (i, j) in self.fixed.interior() {
+            if marking(i, j) == Marking::Solid {
+                self.rho[(i, j)] = 1.0;
+            }
+            if !matches!(marking(i, j), Marking::Fluid | Marking::Dye) {
+                self.s[(i, j)] = 0.0;
+                self.fixed[(i, j)] = 0.0;
+            }
+        }
+
+        let fixed = &self.fixed;
+        self.mask_faces = mask.faces(width, height, self.config.mask_speed, |i, j| {
+            fixed[(i, j)] != 0.0
+        });
+    }
+
+    /// Sets the faces and cells of the fluid within the regions of
+    /// [`SimConfig::initial_velocity`] and [`Scalar::initial`](crate::Scalar::initial) to their
+    /// values. A face takes the velocity when either of its cells lies in the region.
+    fn apply_initial_fields(&mut self) {
+        let (width, height) = (self.width, self.height);
+        let mask = self.config.mask.as_ref();
+        let s = &self.s;
+        let fluid = |i, j| s[(i, j)] != 0.0;
+        for initial in &self.config.initial_velocity {
+            let inside = |i, j| initial.region.contains(i, j, width, height, mask);
+            for (i, j) in s.interior() {
+                if fluid(i, j) && fluid(i - 1, j) && (inside(i, j) || inside(i - 1, j)) {
+                    self.u[(i, j)] = initial.velocity.x;
+                }
+                if fluid(i, j) && fluid(i, j - 1) && (inside(i, j) || inside(i, j - 1)) {
+                    self.v[(i, j)] = initial.velocity.y;
+                }
+            }
+        }
+        for (scalar, field) in self.config.scalars.iter().zip(&mut self.scalars) {
+            for initial in &scalar.initial {
+                for (i, j) in s.interior() {
+                    if fluid(i, j) && initial.region.contains(i, j, width, height, mask) {
+                        field[(i, j)] = initial.value;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn config(&self) -> &SimConfig {
+        &self.config
+    }
+
+    /// Simulated time in seconds since the grid was set up.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// The pressure solver, initially the one selected by [`SimConfig::solver`].
+    pub fn solver(&self) -> &dyn PressureSolver {
+        self.solver.as_ref()
+    }
+
+    /// Replaces the pressure solver, e.g. to compare solvers on the same scene.
+    pub fn set_solver(&mut self, solver: Box<dyn PressureSolver>) {
+        self.solver = solver;
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Cell size in meters.
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    /// Horizontal velocity, `u[(i, j)]` lives on the left face of cell `(i, j)`.
+    pub fn u(&self) -> &Field2 {
+        &self.u
+    }
+
+    /// Vertical velocity, `v[(i, j)]` lives on the bottom face of cell `(i, j)`.
+    pub fn v(&self) -> &Field2 {
+        &self.v
+    }
+
+    /// Solid mask, 1.0 fluid, 0.0 solid.
+    pub fn s(&self) -> &Field2 {
+        &self.s
+    }
+
+    pub fn rho(&self) -> &Field2 {
+        &self.rho
+    }
+
+    /// The passive scalar configured with `name`.
+    pub fn scalar(&self, name: &str) -> Option<&Field2> {
+        let index = self.config.scalars.iter().position(|s| s.name == name)?;
+        Some(&self.scalars[index])
+    }
+
+    pub fn p(&self) -> &Field2 {
+        &self.p
+    }
+
+    /// Signed distance to the free surface in meters, negative in the liquid, or `None` if the
+    /// whole domain is fluid.
+    pub fn phi(&self) -> Option<&Field2> {
+        self.phi.as_ref()
+    }
+
+    /// The particles carrying the velocity if [`SimConfig::flip`] is set.
+    pub fn particles(&self) -> Option<&Particles> {
+        self.particles.as_ref()
+    }
+
+    /// The moving obstacles at their current position.
+    pub fn obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+
+    /// The tracer particles, oldest first.
+    pub fn tracers(&self) -> &[Tracer] {
+        &self.tracers
+    }
+
+    /// A sampler of one of the fields of this grid with the configured boundary policy and
+    /// interpolation.
+    pub fn sampler<'a>(&'a self, field: &'a Field2) -> Sampler<'a> {
+        let config = &self.config;
+        Sampler::new(field, &self.s, config.boundary, config.interpolation)
+    }
+
+    /// Velocity at `position` in meters, where the center of cell `(i, j)` is at
+    /// `(i, j) * size`.
+    pub fn velocity_at(&self, position: Vec2) -> Vec2 {
+        let x = position / self.size;
+        vec2(self.sampler(&self.u).at(x), self.sampler(&self.v).at(x))
+    }
+
+    /// Density at `position` in meters, see [`Grid::velocity_at`].
+    pub fn density_at(&self, position: Vec2) -> f32 {
+        self.sampler(&self.rho).at(position / self.size)
+    }
+
+    /// Largest speed on any face in m/s, NaN once any velocity is NaN.
+    pub fn max_velocity(&self) -> f32 {
+        let speeds = self
+            .u
+            .data()
+            .iter()
+            .chain(self.v.data())
+            .map(|value| value.abs());
+        // Unlike `f32::max`, this keeps a NaN
+        speeds.fold(0.0, |max, speed| {
+            if speed > max || speed.is_nan() {
+                speed
+            } else {
+                max
+            }
+        })
+    }
+
+    /// Longest time step that satisfies [`SimConfig::cfl`] for the current velocity.
+    pub fn cfl_dt(&self) -> f32 {
+        timestep::cfl_dt(self.max_velocity(), self.size, self.config.cfl)
+    }
+
+    /// Advances the simulation by `frame_time` seconds in substeps limited by the CFL condition
+    /// and reports the time steps taken.
+    pub fn advance(&mut self, frame_time: f32) -> StepReport {
+        let mut solve = SolveReport::default();
+        let substeps = timestep::advance(self, frame_time, Grid::cfl_dt, |grid, dt| {
+            solve = grid.step(dt)
+        });
+        StepReport { substeps, solve }
+    }
+
+    /// Advances the simulation by `dt` seconds and reports how the pressure solve went.
+    pub fn step(&mut self, dt: f32) -> SolveReport {
+        let pool = self.pool.clone();
+        pool.install(|| {
+            if !self.obstacles.is_empty() {
+                self.move_obstacles(dt);
+            }
+            self.enforce_boundaries(dt);
+            self.integrate(dt);
+            if self.config.viscosity > 0.0 {
+                self.diffuse_velocity(dt);
+            }
+            let report = self.project(dt);
+            self.extrapolate_velocity();
+            self.advect_tracers(dt);
+            if self.particles.is_some() {
+                self.transport_particles(dt);
+            } else {
+                self.advect_velocity(dt);
+            }
+            self.advect_surface(dt);
+            self.advect_density(dt);
+            self.update_scalars(dt);
+            self.time += dt;
+            report
+        })
+    }
+
+    /// Moves the obstacles of [`SimConfig::obstacles`] by `dt` seconds.
+    fn move_obstacles(&mut self, dt: f32) {
+        let (width, height, size) = (self.width, self.height, self.size);
+        for obstacle in &mut self.obstacles {
+            obstacle.advance(dt, width, height, size);
+        }
+        self.rasterize_obstacles();
+    }
+
+    /// Paints the moving obstacles into the solid mask over the walls and static obstacles, and
+    /// sets the faces they touch to their velocity, which the projection then leaves alone.
+    fn rasterize_obstacles(&mut self) {
+        if self.obstacles.is_empty() {
+            return;
+        }
+        let (width, height, size) = (self.width, self.height, self.size);
+        let nx = width + 2;
+
+        self.s.copy_from(&self.fixed);
+        let mut owner = vec![None; self.s.data().len()];
+        for (i, j) in self.fixed.interior() {
+            let x = vec2(i as f32, j as f32);
+            let inside = |obstacle: &Obstacle| obstacle.contains(x, width, height);
+            if let Some(n) = self.obstacles.iter().position(inside) {
+                owner[j * nx + i] = Some(n);
+                self.s[(i, j)] = 0.0;
+            }
+        }
+
+        let velocity = |n: usize, x: Vec2| self.obstacles[n].velocity_at(x, width, height, size);
+        for j in 1..=height + 1 {
+            for i in 1..=width + 1 {
+                let k = j * nx + i;
+                if let Some(n) = owner[k].or(owner[k - 1]) {
+                    self.u[(i, j)] = velocity(n, vec2(i as f32 - 0.5, j as f32)).x;
+                }
+                if let Some(n) = owner[k].or(owner[k - nx]) {
+                    self.v[(i, j)] = velocity(n, vec2(i as f32, j as f32 - 0.5)).y;
+                }
+            }
+        }
+    }
+
+    /// Sets the velocity across the sides of the domain to what [`SimConfig::boundaries`]
+    /// prescribes at the end of a step of `dt` seconds. Outflows are scaled to carry away what the
+    /// inflows bring in, unless an open side balances the two. The faces of the inflow and outflow
+    /// cells of the mask are held at their speed, and what they bring in net counts as inflow.
+    fn enforce_boundaries(&mut self, dt: f32) {
+        let mut flux_in = 0.0;
+        for face in &self.mask_faces {
+            if self.s[face.cell] != 0.0 {
+                let field = match face.stagger {
+                    Stagger::XFace => &mut self.u,
+                    _ => &mut self.v,
+                };
+                field[face.face] = face.velocity;
+                flux_in += face.inflow();
+            }
+        }
+
+        let time = self.time + dt;
+        let (s, width, height, size) = (&self.s, self.width, self.height, self.size);
+        let mut outlets = Vec::new();
+        let mut open = false;
+        for (side, condition) in self.config.boundaries.iter() {
+            let outward = side.outward();
+            let field = match side {
+                Side::Left | Side::Right => &mut self.u,
+                Side::Bottom | Side::Top => &mut self.v,
+            };
+            let mut faces = boundary::side_faces(side, width, height);
+            faces.retain(|face| s[face.cell] != 0.0);
+            match condition {
+                BoundaryCondition::Wall => {}
+                BoundaryCondition::Open => open = true,
+                BoundaryCondition::Inflow(inflow) => {
+                    for face in &faces {
+                        let speed = inflow.speed_at(face.x, time);
+                        field[face.face] = -outward * speed;
+                        flux_in += speed;
+                    }
+                }
+                BoundaryCondition::Outflow => {
+                    for face in &faces {
+                        field[face.face] = outward * (outward * field[face.inner]).max(0.0);
+                    }
+                }
+                BoundaryCondition::Convective { speed } => {
+                    let mean = || {
+                        let sum: f32 = faces.iter().map(|face| outward * field[face.face]).sum();
+                        sum.max(0.0) / faces.len().max(1) as f32
+                    };
+                    let courant = (speed.unwrap_or_else(mean) * dt / size).clamp(0.0, 1.0);
+                    for face in &faces {
+                        let (out, inner) =
+                            (outward * field[face.face], outward * field[face.inner]);
+                        field[face.face] = outward * (out - courant * (out - inner)).max(0.0);
+                    }
+                }
+            }
+            if condition.is_outlet() {
+                outlets.extend(faces.iter().map(|face| (side, outward, face.face)));
+            }
+        }
+        if open || outlets.is_empty() {
+            return;
+        }
+
+        let outflow: f32 = outlets
+            .iter()
+            .map(|&(side, outward, face)| outward * self.across(side)[face])
+            .sum();
+        for &(side, outward, face) in &outlets {
+            let value = &mut self.across(side)[face];
+            *value = if outflow > 0.0 {
+                *value * flux_in.max(0.0) / outflow
+            } else {
+                outward * flux_in.max(0.0) / outlets.len() as f32
+            };
+        }
+    }
+
+    /// The velocity component across `side`.
+    fn across(&mut self, side: Side) -> &mut Field2 {
+        match side {
+            Side::Left | Side::Right => &mut self.u,
+            Side::Bottom | Side::Top => &mut self.v,
+        }
+    }
+
+    fn integrate(&mut self, dt: f32) {
+        let g = self.config.gravity;
+
+        for j in 1..=self.height {
+            for i in 1..=self.width {
+                if self.s[(i, j)] != 0.0 && self.s[(i, j - 1)] != 0.0 {
+                    self.v[(i, j)] += g * dt;
+                }
+            }
+        }
+
+        if self.config.buoyancy.is_some() {
+            self.apply_buoyancy(dt);
+        }
+        if self.config.vorticity_confinement > 0.0 {
+            self.confine_vorticity(dt);
+        }
+    }
+
+    /// Adds the Boussinesq buoyancy of [`SimConfig::buoyancy`] to the vertical velocity.
+    fn apply_buoyancy(&mut self, dt: f32) {
+        let Some(buoyancy) = &self.config.buoyancy else {
+            return;
+        };
+        let field = |name: &str| {
+            let index = self.config.scalars.iter().position(|s| s.name == name);
+            index.map(|index| &self.scalars[index])
+        };
+        let temperature = field(&buoyancy.temperature).expect("Validated scalar");
+        let smoke = buoyancy
+            .smoke
+            .as_deref()
+            .map(|name| field(name).expect("Validated scalar"));
+
+        let s = &self.s;
+        for j in 1..=self.height {
+            for i in 1..=self.width {
+                if s[(i, j)] == 0.0 || s[(i, j - 1)] == 0.0 {
+                    continue;
+                }
+                let face = |f: &Field2| (f[(i, j)] + f[(i, j - 1)]) / 2.0;
+                let mut force = buoyancy.beta * (face(temperature) - buoyancy.ambient);
+                if let Some(smoke) = smoke {
+                    force -= buoyancy.alpha * face(smoke);
+                }
+                self.v[(i, j)] += force * dt;
+            }
+        }
+    }
+
+    /// Adds the vorticity confinement force of Fedkiw, Stam and Jensen, which spins up the small
+    /// vortices that advection smears out. Cells next to the walls are left alone.
+    fn confine_vorticity(&mut self, dt: f32) {
+        let (u, v, size) = (&self.u, &self.v, self.size);
+        let (nx, ny) = (self.width + 2, self.height + 2);
+
+        let velocity = |i: usize, j: usize| {
+            let u = (u[(i, j)] + u[(i + 1, j)]) / 2.0;
+            let v = (v[(i, j)] + v[(i, j + 1)]) / 2.0;
+            vec2(u, v)
+        };
+        let mut curl = Field2::new(nx, ny, Stagger::Center, 0.0);
+        for j in 2..ny - 2 {
+            for i in 2..nx - 2 {
+                if self.s[(i, j)] == 0.0 {
+                    continue;
+                }
+                let dv_dx = velocity(i + 1, j).y - velocity(i - 1, j).y;
+                let du_dy = velocity(i, j + 1).x - velocity(i, j - 1).x;
+                curl[(i, j)] = (dv_dx - du_dy) / (2.0 * size);
+            }
+        }
+
+        // The force points from weaker towards stronger vortices, perpendicular to the curl.
+        let epsilon = self.config.vorticity_confinement;
+        let mut force = vec![Vec2::ZERO; nx * ny];
+        for j in 3..ny.saturating_sub(3) {
+            for i in 3..nx.saturating_sub(3) {
+                let gradient = vec2(
+                    curl[(i + 1, j)].abs() - curl[(i - 1, j)].abs(),
+                    curl[(i, j + 1)].abs() - curl[(i, j - 1)].abs(),
+                );
+                let n = gradient.normalize_or_zero();
+                let w = curl[(i, j)];
+                force[j * nx + i] = epsilon * size * vec2(n.y * w, -n.x * w);
+            }
+        }
+
+        let s = &self.s;
+        for j in 1..=self.height {
+            for i in 1..=self.width {
+                let k = j * nx + i;
+                if s[(i, j)] != 0.0 && s[(i - 1, j)] != 0.0 {
+                    self.u[(i, j)] += dt * (force[k - 1].x + force[k].x) / 2.0;
+                }
+                if s[(i, j)] != 0.0 && s[(i, j - 1)] != 0.0 {
+                    self.v[(i, j)] += dt * (force[k - nx].y + force[k].y) / 2.0;
+                }
+            }
+        }
+    }
+
+    /// Implicit viscous diffusion of the velocity. Walls are no-slip and the inflow drags its
+    /// neighbours along.
+    fn diffuse_velocity(&mut self, dt: f32) {
+        let a = self.config.viscosity * dt / (self.size * self.size);
+        let (s, width, height) = (&self.s, self.width, self.height);
+        let iterations = self.config.iterations;
+
+        let u_unknown = |i: usize, j: usize| {
+            let inside = (2..=width).contains(&i) && (1..=height).contains(&j);
+            inside && s[(i, j)] != 0.0 && s[(i - 1, j)] != 0.0
+        };
+        diffusion::diffuse(&mut self.u, u_unknown, a, Condition::Dirichlet, iterations);
+
+        let v_unknown = |i: usize, j: usize| {
+            let inside = (1..=width).contains(&i) && (2..=height).contains(&j);
+            inside && s[(i, j)] != 0.0 && s[(i, j - 1)] != 0.0
+        };
+        diffusion::diffuse(&mut self.v, v_unknown, a, Condition::Dirichlet, iterations);
+    }
+
+    fn project(&mut self, dt: f32) -> SolveReport {
+        self.solver.project(Projection {
+            u: &mut self.u,
+            v: &mut self.v,
+            s: &self.s,
+            phi: self.phi.as_ref(),
+            p: &mut self.p,
+            size: self.size,
+            dt,
+            density: self.config.density,
+            tolerance: self.config.tolerance,
+            residual_norm: self.config.residual_norm,
+            max_iterations: self.config.iterations,
+        })
+    }
+
+    /// Extends the velocity of the liquid across the free surface, so that it can be sampled in
+    /// the air, as far as the fluid moves in one step. Faces further into the air are cleared.
+    fn extrapolate_velocity(&mut self) {
+        let Some(phi) = &self.phi else {
+            return;
+        };
+        let s = &self.s;
+        let layers = self.config.cfl.ceil() as usize + 2;
+        let fluid = |(i, j): (usize, usize)| s[(i, j)] != 0.0;
+        let liquid = |(i, j): (usize, usize)| phi[(i, j)] < 0.0;
+
+        // The cells on either side of a u or v face
+        let u_cells: fn(usize, usize) -> Option<_> = |i, j| (i > 0).then(|| ((i - 1, j), (i, j)));
+        let v_cells: fn(usize, usize) -> Option<_> = |i, j| (j > 0).then(|| ((i, j - 1), (i, j)));
+        for (field, cells) in [(&mut self.u, u_cells), (&mut self.v, v_cells)] {
+            let open = |i, j| cells(i, j).filter(|&(a, b)| fluid(a) && fluid(b));
+            let known = |i, j| open(i, j).is_some_and(|(a, b)| liquid(a) || liquid(b));
+            let air = |i, j| open(i, j).is_some_and(|(a, b)| !liquid(a) && !liquid(b));
+            for j in 0..field.ny() {
+                for i in 0..field.nx() {
+                    if air(i, j) {
+                        field[(i, j)] = 0.0;
+                    }
+                }
+            }
+            level_set::extrapolate(field, known, air, layers);
+        }
+    }
+
+    fn advect_velocity(&mut self, dt: f32) {
+        let pu = self.u.clone();
+        let pv = self.v.clone();
+        let (s, width, height) = (&self.s, self.width, self.height);
+        let scheme = self.config.velocity_advection;
+        let path = Path {
+            backtrace: self.config.velocity_backtrace,
+            u: &pu,
+            v: &pv,
+            s,
+            boundary: self.config.boundary,
+            interpolation: self.config.interpolation,
+        };
+        let step = dt / self.size;
+
+        // Advect u; indices i = 0, 1 width + 1 are in/on the wall
+        let u_velocity = |i: usize, j: usize| {
+            let inside = (2..=width).contains(&i) && (1..=height).contains(&j);
+            (inside && s[(i, j)] != 0.0 && s[(i - 1, j)] != 0.0).then(|| {
+                // We align these vectors to the u grid
+                let v = (pv[(i - 1, j)] + pv[(i, j)] + pv[(i - 1, j + 1)] + pv[(i, j + 1)]) / 4.0;
+                vec2(pu[(i, j)], v)
+            })
+        };
+        advect::advect(&mut self.u, scheme, &path, &u_velocity, step);
+
+        // Advect v; indices j = 0, 1, height + 1 are in/on the wall
+        let v_velocity = |i: usize, j: usize| {
+            let inside = (1..=width).contains(&i) && (2..=height).contains(&j);
+            (inside && s[(i, j)] != 0.0 && s[(i, j - 1)] != 0.0).then(|| {
+                let u = (pu[(i, j - 1)] + pu[(i + 1, j - 1)] + pu[(i, j)] + pu[(i + 1, j)]) / 4.0;
+                vec2(u, pv[(i, j)])
+            })
+        };
+        advect::advect(&mut self.v, scheme, &path, &v_velocity, step);
+    }
+
+    /// Moves the velocity with the FLIP/PIC particles: they pick up the projected grid velocity,
+    /// move through it and are transferred back to the grid for the next step.
+    fn transport_particles(&mut self, dt: f32) {
+        let (Some(flip), Some(mut particles)) = (self.config.flip, self.particles.take()) else {
+            return;
+        };
+        let boundary = self.config.boundary;
+        particles.from_grid(&self.u, &self.v, &self.s, boundary, flip.ratio);
+        let fluid = |i, j| self.s[(i, j)] != 0.0;
+        particles.advect(&self.u, &self.v, &self.s, boundary, dt / self.size, fluid);
+        self.particles = Some(particles);
+        self.transfer_to_grid();
+    }
+
+    /// Refills cells that ran out of particles, thins out crowded ones and transfers the particle
+    /// velocities to the faces between fluid cells.
+    fn transfer_to_grid(&mut self) {
+        let (Some(flip), Some(mut particles)) = (self.config.flip, self.particles.take()) else {
+            return;
+        };
+        let (s, phi, width, height) = (&self.s, &self.phi, self.width, self.height);
+        let seed = |i, j| s[(i, j)] != 0.0 && phi.as_ref().is_none_or(|phi| phi[(i, j)] < 0.0);
+        particles.reseed(flip.per_axis, seed, |x| self.velocity_at(x * self.size));
+
+        let open_u = |i: usize, j: usize| {
+            let inside = (2..=width).contains(&i) && (1..=height).contains(&j);
+            inside && s[(i, j)] != 0.0 && s[(i - 1, j)] != 0.0
+        };
+        let open_v = |i: usize, j: usize| {
+            let inside = (1..=width).contains(&i) && (2..=height).contains(&j);
+            inside && s[(i, j)] != 0.0 && s[(i, j - 1)] != 0.0
+        };
+        particles.to_grid(&mut self.u, &mut self.v, open_u, open_v);
+        self.particles = Some(particles);
+    }
+
+    /// Moves the tracers through the projected velocity, removes those that hit a solid and
+    /// releases new ones at the emitters.
+    fn advect_tracers(&mut self, dt: f32) {
+        let (config, s) = (&self.config, &self.s);
+        let sampler = |field| Sampler::new(field, s, config.boundary, config.interpolation);
+        let (u, v) = (sampler(&self.u), sampler(&self.v));
+        let velocity = |x: Vec2| vec2(u.at(x), v.at(x));
+        let (step, tracers) = (dt / self.size, &config.tracers);
+        self.tracers.par_iter_mut().for_each(|tracer| {
+            tracer.advance(velocity, step, tracers.integrator, tracers.trail);
+        });
+
+        let fluid = |(i, j)| s.get(i, j).is_some_and(|s| s != 0.0);
+        self.tracers
+            .retain(|tracer| tracer.cell().is_some_and(fluid));
+        tracers.release(self.width, self.height, false, &mut self.tracers);
+        let excess = self.tracers.len().saturating_sub(tracers.limit);
+        self.tracers.drain(..excess);
+    }
+
+    /// Moves the free surface with the flow and restores the level set to a signed distance.
+    fn advect_surface(&mut self, dt: f32) {
+        let Some(phi) = &mut self.phi else {
+            return;
+        };
+        advect_scalar(phi, &self.u, &self.v, &self.s, &self.config, dt / self.size);
+        level_set::redistance(phi, &self.s, self.size);
+    }
+
+    fn advect_density(&mut self, dt: f32) {
+        let step = dt / self.size;
+        advect_scalar(&mut self.rho, &self.u, &self.v, &self.s, &self.config, step);
+    }
+
+    /// Emits, advects and diffuses the passive scalars.
+    fn update_scalars(&mut self, dt: f32) {
+        let (s, width, height) = (&self.s, self.width, self.height);
+        let fluid = |i: usize, j: usize| {
+            let inside = (1..=width).contains(&i) && (1..=height).contains(&j);
+            inside && s[(i, j)] != 0.0
+        };
+        let mask = self.config.mask.as_ref();
+        let buoyancy = self.config.buoyancy.as_ref();
+
+        for (scalar, field) in self.config.scalars.iter().zip(&mut self.scalars) {
+            let perturbation = buoyancy
+                .filter(|buoyancy| buoyancy.temperature == scalar.name)
+                .map_or(0.0, |buoyancy| buoyancy.perturbation);
+            for emitter in &scalar.emitters {
+                for (i, j) in s.interior() {
+                    if emitter.region.contains(i, j, width, height, mask) && fluid(i, j) {
+                        let noise = 2.0 * scalar::jitter(s.index(i, j)) - 1.0;
+                        field[(i, j)] = emitter.value + perturbation * noise;
+                    }
+                }
+            }
+
+            advect_scalar(field, &self.u, &self.v, s, &self.config, dt / self.size);
+
+            if scalar.diffusion > 0.0 {
+                let a = scalar.diffusion * dt / (self.size * self.size);
+                let iterations = self.config.iterations;
+                diffusion::diffuse(field, fluid, a, Condition::Neumann, iterations);
+            }
+        }
+    }
+
+    /// Draws the grid with macroquad, 5 pixels per cell.
+    pub fn render(&self) {
+        let pixels = 5.0;
+        let start = vec2(0.0 - pixels, 500.0 + pixels);
+
+        for j in 1..=self.height {
+            for i in 1..=self.width {
+                let u = (self.u[(i, j)] + self.u[(i + 1, j)]) / 2.0;
+                let v = (self.v[(i, j)] + self.v[(i, j + 1)]) / 2.0;
+                let pos = start + vec2(i as f32 * pixels, 0.0) - vec2(0.0, j as f32 * pixels);
+
+                // Velocity
+                let _speed = vec2(u, v).length();
+                let norm = 10.0;
+                //let color = [u.abs() / norm, v.abs() / norm, speed / norm, 1.0].into();
+                let mut color: Color = [u.abs() / norm, 0.0, v.abs() / norm, 1.0].into();
+
+                // Free surface
+                match &self.phi {
+                    Some(phi) if phi[(i, j)] >= 0.0 => color = DARKGRAY,
+                    Some(_) => color.b = 0.5 + color.b / 2.0,
+                    None => {}
+                }
+
+                // Obstacles
+                if self.s[(i, j)] == 0.0 {
+                    color = GRAY;
+                }
+                //let color: Color = [u / norm, 0.0, -u / norm, 1.0].into();
+
+                // Pressure
+                let _color: Color = [self.p[(i, j)] / 100000.0, 0.0, 0.0, 1.0].into();
+
+                // Density
+                let _rho = self.rho[(i, j)];
+                //let color = [rho, 0.0, 0.0, 1.0].into();
+
+                let color = self.blend_scalars(i, j, color);
+                draw_rectangle(pos.x, pos.y, pixels, pixels, color);
+            }
+        }
+
+        for j in 1..=self.height {
+            for i in 1..=self.width {
+                let u = (self.u[(i, j)] + self.u[(i + 1, j)]) / 2.0;
+                let v = (self.v[(i, j)] + self.v[(i, j + 1)]) / 2.0;
+                let pos = start + vec2(i as f32 * pixels, 0.0) - vec2(0.0, j as f32 * pixels);
+                let pos = pos + vec2(pixels / 2.0, -pixels / 2.0);
+                let d = vec2(u, v) * 2.0;
+                let color: Color = [0.0, 0.0, 1.0, 0.3].into();
+                draw_line(pos.x, pos.y, pos.x + d.x, pos.y - d.y, 1.0, color);
+            }
+        }
+
+        // Tracers, with trails fading out towards their oldest positions
+        let screen = |x: Vec2| start + vec2(x.x + 0.5, 0.5 - x.y) * pixels;
+        for tracer in &self.tracers {
+            let points = tracer.trail.iter().chain([&tracer.position]);
+            let count = tracer.trail.len() as f32;
+            for (n, (a, b)) in points.clone().zip(points.skip(1)).enumerate() {
+                let (a, b) = (screen(*a), screen(*b));
+                let color = Color::new(1.0, 1.0, 1.0, (n + 1) as f32 / count);
+                draw_line(a.x, a.y, b.x, b.y, 1.0, color);
+            }
+            let x = screen(tracer.position);
+            draw_circle(x.x, x.y, 1.0, WHITE);
+        }
+    }
+
+    /// Mixes the colors of the passive scalars in cell `(i, j)` weighted by their concentrations,
+    /// over `background` where the total concentration is below 1.
+    fn blend_scalars(&self, i: usize, j: usize, background: Color) -> Color {
+        let mut total = 0.0;
+        let mut dye = Vec4::ZERO;
+        for (scalar, field) in self.config.scalars.iter().zip(&self.scalars) {
+            let amount = field[(i, j)].max(0.0);
+            total += amount;
+            dye += amount * scalar.color.to_vec();
+        }
+        if total == 0.0 {
+            return background;
+        }
+        let alpha = total.min(1.0);
+        let color = background.to_vec() * (1.0 - alpha) + dye / total * alpha;
+        Color::from_vec(color)
+    }
+}
+
+/// Advects a cell centered `field` by `step = dt / size` cells with the velocity `u`, `v`.
+fn advect_scalar(
+    field: &mut Field2,
+    u: &Field2,
+    v: &Field2,
+    s: &Field2,
+    config: &SimConfig,
+    step: f32,
+) {
+    let (width, height) = (s.nx() - 2, s.ny() - 2);
+    let velocity = |i: usize, j: usize| {
+        let inside = (1..=width).contains(&i) && (1..=height).contains(&j);
+        (inside && s[(i, j)] != 0.0).then(|| {
+            let u = (u[(i, j)] + u[(i + 1, j)]) / 2.0;
+            let v = (v[(i, j)] + v[(i, j + 1)]) / 2.0;
+            vec2(u, v)
+        })
+    };
+    let path = Path {
+        backtrace: config.scalar_backtrace,
+        u,
+        v,
+        s,
+        boundary: config.boundary,
+        interpolation: config.interpolation,
+    };
+    advect::advect(field, config.scalar_advection, &path, &velocity, step);
+}
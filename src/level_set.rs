@@ -0,0 +1,159 @@
+//! Level sets tracking a free liquid surface: signed distances that are negative in the liquid,
+//! and the extrapolation of fields across the surface.
+
+use macroquad::prelude::*;
+
+use crate::config::Disk;
+use crate::field::Field2;
+
+/// A shape of liquid at the start of a free surface simulation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Liquid {
+    /// Axis aligned rectangle between two corners given as fractions of the domain, e.g. the
+    /// water column of a dam break.
+    Rect { min: Vec2, max: Vec2 },
+    /// A drop, centered and sized like an obstacle.
+    Disk(Disk),
+}
+
+impl Liquid {
+    /// Signed distance in cells from cell center `x` to the surface of the shape on a grid of
+    /// `width` x `height` cells, negative inside.
+    pub fn distance(&self, x: Vec2, width: usize, height: usize) -> f32 {
+        let scale = vec2(width as f32, height as f32);
+        match self {
+            Liquid::Rect { min, max } => {
+                let (min, max) = (*min * scale, *max * scale);
+                let q = (x - (min + max) / 2.0).abs() - (max - min) / 2.0;
+                q.max(Vec2::ZERO).length() + q.max_element().min(0.0)
+            }
+            Liquid::Disk(disk) => {
+                let (center, radius) = disk.in_cells(width, height);
+                x.distance(center) - radius
+            }
+        }
+    }
+}
+
+/// Signed distance in meters to the union of the `liquid` shapes at every sample of `phi`, on a
+/// grid of cells of side `size`.
+pub fn initialize(phi: &mut Field2, liquid: &[Liquid], size: f32) {
+    let (width, height) = (phi.nx() - 2, phi.ny() - 2);
+    for j in 0..phi.ny() {
+        for i in 0..phi.nx() {
+            let x = vec2(i as f32, j as f32);
+            let distance = liquid
+                .iter()
+                .map(|shape| shape.distance(x, width, height))
+                .fold(f32::INFINITY, f32::min);
+            phi[(i, j)] = distance * size;
+        }
+    }
+}
+
+/// Turns `phi` back into a signed distance over the fluid cells of `s` without moving its zero
+/// crossing, which advection distorts over time.
+///
+/// Cells next to the surface get their distance from the linear crossing with their neighbours,
+/// all others by fast sweeping of the eikonal equation `|∇φ| = 1`. Solid cells take the average
+/// of their fluid neighbours, so samplers see no stale surface in walls and obstacles.
+pub fn redistance(phi: &mut Field2, s: &Field2, size: f32) {
+    let (nx, ny) = (phi.nx(), phi.ny());
+    let fluid = |i: usize, j: usize| s[(i, j)] != 0.0;
+    let neighbours = |i: usize, j: usize| [(i - 1, j), (i + 1, j), (i, j - 1), (i, j + 1)];
+
+    // Distance in cells and whether it is fixed by the surface
+    let mut distance = vec![f32::INFINITY; nx * ny];
+    let mut frozen = vec![false; nx * ny];
+    for (i, j) in phi.interior() {
+        if !fluid(i, j) {
+            continue;
+        }
+        let here = phi[(i, j)];
+        for (ni, nj) in neighbours(i, j) {
+            let there = phi[(ni, nj)];
+            if fluid(ni, nj) && (here < 0.0) != (there < 0.0) {
+                let k = phi.index(i, j);
+                distance[k] = distance[k].min(here.abs() / (here - there).abs());
+                frozen[k] = true;
+            }
+        }
+    }
+
+    let (width, height) = (nx - 2, ny - 2);
+    for (reverse_i, reverse_j) in [(false, false), (true, false), (false, true), (true, true)] {
+        for j in 1..=height {
+            let j = if reverse_j { height + 1 - j } else { j };
+            for i in 1..=width {
+                let i = if reverse_i { width + 1 - i } else { i };
+                let k = phi.index(i, j);
+                if frozen[k] || !fluid(i, j) {
+                    continue;
+                }
+                let a = distance[k - 1].min(distance[k + 1]);
+                let b = distance[k - nx].min(distance[k + nx]);
+                let solution = if (a - b).abs() >= 1.0 {
+                    a.min(b) + 1.0
+                } else {
+                    (a + b + (2.0 - (a - b) * (a - b)).sqrt()) / 2.0
+                };
+                distance[k] = distance[k].min(solution);
+            }
+        }
+    }
+
+    for (i, j) in phi.interior() {
+        let k = phi.index(i, j);
+        if fluid(i, j) && distance[k].is_finite() {
+            phi[(i, j)] = phi[(i, j)].signum() * distance[k] * size;
+        }
+    }
+    extrapolate(phi, fluid, |i, j| !fluid(i, j), 2);
+}
+
+/// Extends `field` from the samples where `known(i, j)` holds into those where `unknown(i, j)`
+/// holds, one layer of neighbours at a time: each sample next to known ones takes their average
+/// and becomes known. After `layers` layers the remaining unknown samples are left as they are.
+pub fn extrapolate(
+    field: &mut Field2,
+    known: impl Fn(usize, usize) -> bool,
+    unknown: impl Fn(usize, usize) -> bool,
+    layers: usize,
+) {
+    let (nx, ny) = (field.nx(), field.ny());
+    let mut valid: Vec<bool> = (0..nx * ny).map(|k| known(k % nx, k / nx)).collect();
+    let mut layer = Vec::new();
+    for _ in 0..layers {
+        layer.clear();
+        for j in 0..ny {
+            for i in 0..nx {
+                let k = field.index(i, j);
+                if valid[k] || !unknown(i, j) {
+                    continue;
+                }
+                let neighbours = [
+                    (i.wrapping_sub(1), j),
+                    (i + 1, j),
+                    (i, j.wrapping_sub(1)),
+                    (i, j + 1),
+                ];
+                let (sum, count) = neighbours
+                    .iter()
+                    .filter(|&&(i, j)| i < nx && j < ny && valid[j * nx + i])
+                    .fold((0.0, 0), |(sum, count), &(i, j)| {
+                        (sum + field[(i, j)], count + 1)
+                    });
+                if count > 0 {
+                    layer.push((k, sum / count as f32));
+                }
+            }
+        }
+        if layer.is_empty() {
+            break;
+        }
+        for &(k, value) in &layer {
+            field.data_mut()[k] = value;
+            valid[k] = true;
+        }
+    }
+}
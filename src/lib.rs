@@ -0,0 +1,38 @@
+//! Eulerian fluid simulation on a staggered (MAC) grid, with a CPU solver in [`Grid`] and a
+//! wgpu compute implementation in [`gpu`].
+
+#![allow(clippy::needless_range_loop)]
+
+pub mod advect;
+pub mod boundary;
+pub mod config;
+pub mod diffusion;
+pub mod field;
+pub mod flip;
+pub mod gpu;
+pub mod grid;
+pub mod level_set;
+pub mod mask;
+pub mod obstacle;
+pub mod pressure;
+pub mod sampler;
+pub mod scalar;
+pub mod scene;
+pub mod timestep;
+pub mod tracer;
+
+pub use advect::{Backtrace, Scheme};
+pub use boundary::{Boundaries, BoundaryCondition, Inflow, Profile, Variation};
+pub use config::{Buoyancy, ConfigError, Disk, InitialVelocity, Side, SimConfig};
+pub use field::{Field2, Stagger};
+pub use flip::{Flip, Particles};
+pub use grid::Grid;
+pub use level_set::Liquid;
+pub use mask::{Marking, Mask};
+pub use obstacle::{Obstacle, Shape};
+pub use pressure::{Cycle, PressureSolver, Projection, ResidualNorm, SolveReport, Solver};
+pub use sampler::{Boundary, Interpolation, Sampler};
+pub use scalar::{Emitter, Region, Scalar};
+pub use scene::{Scene, SceneError};
+pub use timestep::{FixedStep, Pacing, StepReport, Substeps};
+pub use tracer::{Integrator, Seed, Tracer, Tracers};
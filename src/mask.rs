@@ -0,0 +1,156 @@
+//! Scene geometry painted in an image: walls and obstacles in black, with color-coded regions
+//! for the inflow, the outflow and dye sources.
+
+use std::path::Path;
+
+use image::{ImageResult, RgbaImage};
+
+use crate::field::Stagger;
+
+/// What a pixel of a [`Mask`] marks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Marking {
+    /// White, light gray or transparent.
+    Fluid,
+    /// Black or dark gray.
+    Solid,
+    /// Red: solid cells blowing fluid into their fluid neighbours.
+    Inflow,
+    /// Blue: solid cells drawing fluid out of their fluid neighbours.
+    Outflow,
+    /// Green: fluid cells emitting the scalars with a [`Region::Mask`](crate::Region::Mask)
+    /// emitter.
+    Dye,
+}
+
+impl Marking {
+    fn classify([r, g, b, a]: [u8; 4]) -> Marking {
+        if a < 128 {
+            return Marking::Fluid;
+        }
+        let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+        if max - min >= 128 {
+            return if max == r {
+                Marking::Inflow
+            } else if max == g {
+                Marking::Dye
+            } else {
+                Marking::Outflow
+            };
+        }
+        let luma = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+        if luma < 128 {
+            Marking::Solid
+        } else {
+            Marking::Fluid
+        }
+    }
+}
+
+/// A face between an inflow or outflow cell of a [`Mask`] and one of its fluid neighbours.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct MaskFace {
+    /// The fluid neighbour.
+    pub cell: (usize, usize),
+    /// [`Stagger::XFace`] for a `u` face, [`Stagger::YFace`] for a `v` face.
+    pub stagger: Stagger,
+    pub face: (usize, usize),
+    /// Velocity the face is held at, positive to the right and up.
+    pub velocity: f32,
+}
+
+impl MaskFace {
+    /// Velocity into the fluid neighbour, which lies right of or above the face when they share
+    /// an index.
+    pub fn inflow(&self) -> f32 {
+        if self.face == self.cell {
+            self.velocity
+        } else {
+            -self.velocity
+        }
+    }
+}
+
+/// An image of the scene stretched over the interior of the grid, whatever its resolution.
+/// The top row of the image is the top row of cells.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mask {
+    width: usize,
+    height: usize,
+    markings: Vec<Marking>,
+}
+
+impl Mask {
+    /// Loads a PNG or PGM image.
+    pub fn load(path: impl AsRef<Path>) -> ImageResult<Mask> {
+        Ok(Mask::from_image(&image::open(path)?.to_rgba8()))
+    }
+
+    pub fn from_image(image: &RgbaImage) -> Mask {
+        Mask {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            markings: image.pixels().map(|p| Marking::classify(p.0)).collect(),
+        }
+    }
+
+    /// Size of the image in pixels.
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Whether any pixel carries `marking`.
+    pub fn contains(&self, marking: Marking) -> bool {
+        self.markings.contains(&marking)
+    }
+
+    /// Marking of interior cell `(i, j)` of a grid of `width` x `height` cells, from the pixel
+    /// nearest to its center.
+    pub fn at(&self, i: usize, j: usize, width: usize, height: usize) -> Marking {
+        let x = (i as f32 - 0.5) / width as f32 * self.width as f32;
+        let y = (height as f32 - j as f32 + 0.5) / height as f32 * self.height as f32;
+        let x = (x as usize).min(self.width - 1);
+        let y = (y as usize).min(self.height - 1);
+        self.markings[y * self.width + x]
+    }
+
+    /// The faces between the inflow and outflow cells and their fluid neighbours among the
+    /// interior cells, blowing into the neighbours of inflow cells and drawing out of the
+    /// neighbours of outflow cells at `speed`.
+    pub(crate) fn faces(
+        &self,
+        width: usize,
+        height: usize,
+        speed: f32,
+        fluid: impl Fn(usize, usize) -> bool,
+    ) -> Vec<MaskFace> {
+        let mut faces = Vec::new();
+        for j in 1..=height {
+            for i in 1..=width {
+                let speed = match self.at(i, j, width, height) {
+                    Marking::Inflow => speed,
+                    Marking::Outflow => -speed,
+                    _ => continue,
+                };
+                let neighbours = [
+                    ((i + 1, j), Stagger::XFace, (i + 1, j), speed),
+                    ((i - 1, j), Stagger::XFace, (i, j), -speed),
+                    ((i, j + 1), Stagger::YFace, (i, j + 1), speed),
+                    ((i, j - 1), Stagger::YFace, (i, j), -speed),
+                ];
+                for (cell, stagger, face, velocity) in neighbours {
+                    let interior = (1..=width).contains(&cell.0) && (1..=height).contains(&cell.1);
+                    if interior && fluid(cell.0, cell.1) {
+                        faces.push(MaskFace {
+                            cell,
+                            stagger,
+                            face,
+                            velocity,
+                        });
+                    }
+                }
+            }
+        }
+        faces
+    }
+}
@@ -0,0 +1,103 @@
+//! Rigid obstacles moving with a prescribed velocity.
+
+use macroquad::prelude::*;
+
+/// Outline of an [`Obstacle`] in cell units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    Disk {
+        radius: f32,
+    },
+    /// A rectangle extending `half_size` from the center along its own axes, e.g. a paddle.
+    Rect {
+        half_size: Vec2,
+    },
+}
+
+/// A rigid obstacle that moves and turns at a constant rate, see
+/// [`SimConfig::obstacles`](crate::SimConfig::obstacles).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Obstacle {
+    pub shape: Shape,
+    /// Center as a fraction of the domain, like the center of a [`Disk`](crate::Disk).
+    pub center: Vec2,
+    /// Counterclockwise rotation in radians.
+    pub angle: f32,
+    /// Velocity of the center in m/s.
+    pub velocity: Vec2,
+    /// Counterclockwise rotation rate around the center in rad/s.
+    pub angular_velocity: f32,
+}
+
+impl Obstacle {
+    /// A disk of `radius` cells at rest at `center`.
+    pub fn disk(center: Vec2, radius: f32) -> Obstacle {
+        Obstacle {
+            shape: Shape::Disk { radius },
+            center,
+            angle: 0.0,
+            velocity: Vec2::ZERO,
+            angular_velocity: 0.0,
+        }
+    }
+
+    /// A rectangle of `half_size` cells at rest at `center`.
+    pub fn rect(center: Vec2, half_size: Vec2) -> Obstacle {
+        Obstacle {
+            shape: Shape::Rect { half_size },
+            ..Obstacle::disk(center, 0.0)
+        }
+    }
+
+    pub fn with_velocity(mut self, velocity: Vec2) -> Obstacle {
+        self.velocity = velocity;
+        self
+    }
+
+    pub fn with_angular_velocity(mut self, angular_velocity: f32) -> Obstacle {
+        self.angular_velocity = angular_velocity;
+        self
+    }
+
+    /// Whether the center lies within the domain and all parameters are finite and sizes
+    /// non-negative.
+    pub(crate) fn is_valid(&self) -> bool {
+        let unit = 0.0..=1.0;
+        let inside = unit.contains(&self.center.x) && unit.contains(&self.center.y);
+        let size = match self.shape {
+            Shape::Disk { radius } => radius >= 0.0 && radius.is_finite(),
+            Shape::Rect { half_size } => half_size.min_element() >= 0.0 && half_size.is_finite(),
+        };
+        let motion = self.angle.is_finite()
+            && self.velocity.is_finite()
+            && self.angular_velocity.is_finite();
+        inside && size && motion
+    }
+
+    /// Center in cell units on a grid of `width` x `height` cells.
+    pub fn center_in_cells(&self, width: usize, height: usize) -> Vec2 {
+        self.center * vec2(width as f32, height as f32)
+    }
+
+    /// Whether position `x` in cell units lies within the obstacle.
+    pub fn contains(&self, x: Vec2, width: usize, height: usize) -> bool {
+        let r = Vec2::from_angle(-self.angle).rotate(x - self.center_in_cells(width, height));
+        match self.shape {
+            Shape::Disk { radius } => r.length_squared() < radius * radius,
+            Shape::Rect { half_size } => r.abs().cmplt(half_size).all(),
+        }
+    }
+
+    /// Velocity of the obstacle at position `x` in cell units, on cells of side `size`.
+    pub fn velocity_at(&self, x: Vec2, width: usize, height: usize, size: f32) -> Vec2 {
+        let r = (x - self.center_in_cells(width, height)) * size;
+        self.velocity + self.angular_velocity * r.perp()
+    }
+
+    /// Moves and turns the obstacle by `dt` seconds on a grid of `width` x `height` cells of
+    /// side `size`.
+    pub fn advance(&mut self, dt: f32, width: usize, height: usize, size: f32) {
+        self.center += self.velocity * dt / (vec2(width as f32, height as f32) * size);
+        self.angle += self.angular_velocity * dt;
+    }
+}
@@ -0,0 +1,71 @@
+//! Jacobi relaxation on the velocity faces, the CPU counterpart of the GPU `gather` and
+//! `scatter_*` passes in `compute.wgsl`.
+
+use crate::pressure::{PressureSolver, Projection, SolveReport};
+
+/// Simultaneous relaxation of all cells: every iteration first gathers the divergence of each
+/// liquid cell and then scatters it to the surrounding faces.
+#[derive(Clone, Debug)]
+pub struct Jacobi {
+    /// Fraction of the divergence removed per iteration. The GPU removes all of it, which never
+    /// damps a checkerboard pattern in the divergence, so the solve stalls above any tolerance.
+    pub weight: f32,
+    avg_div: Vec<f32>,
+}
+
+impl Default for Jacobi {
+    fn default() -> Jacobi {
+        Jacobi {
+            weight: 0.8,
+            avg_div: Vec::new(),
+        }
+    }
+}
+
+impl PressureSolver for Jacobi {
+    fn name(&self) -> &'static str {
+        "Jacobi"
+    }
+
+    fn project(&mut self, mut projection: Projection) -> SolveReport {
+        let weight = self.weight;
+        let avg_div = &mut self.avg_div;
+        avg_div.clear();
+        avg_div.resize(projection.s.data().len(), 0.0);
+        let liquid = projection.liquid();
+
+        projection.iterate(|projection| {
+            let scale = projection.pressure_scale();
+            let s = projection.s;
+            let row = s.nx();
+
+            // Gather
+            for (i, j) in s.interior() {
+                let k = s.index(i, j);
+                let ss = s[(i - 1, j)] + s[(i + 1, j)] + s[(i, j - 1)] + s[(i, j + 1)];
+                avg_div[k] = if s[(i, j)] != 0.0 && ss != 0.0 && liquid(k) {
+                    let u = projection.u.data();
+                    let v = projection.v.data();
+                    weight * (u[k + 1] - u[k] + v[k + row] - v[k]) / ss
+                } else {
+                    0.0
+                };
+            }
+
+            // Scatter
+            let s = s.data();
+            let u = projection.u.data_mut();
+            let v = projection.v.data_mut();
+            for (k, &d) in avg_div.iter().enumerate() {
+                if d == 0.0 {
+                    continue;
+                }
+                u[k] += d * s[k - 1];
+                u[k + 1] -= d * s[k + 1];
+                v[k] += d * s[k - row];
+                v[k + row] -= d * s[k + row];
+                projection.p.data_mut()[k] += d * scale;
+            }
+        })
+    }
+}
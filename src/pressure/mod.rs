@@ -0,0 +1,295 @@
+use serde::Deserialize;
+
+use crate::config::SimConfig;
+use crate::field::Field2;
+
+pub mod jacobi;
+pub mod multigrid;
+pub mod pcg;
+pub mod sor;
+
+pub use jacobi::Jacobi;
+pub use multigrid::{Cycle, Multigrid};
+pub use pcg::Pcg;
+pub use sor::{RedBlack, Sor};
+
+/// Residual reduction of the PCG and multigrid solvers without an explicit tolerance.
+pub const RELATIVE_TOLERANCE: f64 = 1e-6;
+
+/// Smallest fraction of a face that the ghost fluid method lets lie in the liquid, so thin
+/// slivers of liquid do not make the pressure system arbitrarily stiff.
+const MIN_LIQUID_FRACTION: f64 = 0.01;
+
+/// Pressure solver selected in the [`SimConfig`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Solver {
+    /// In-place Gauss-Seidel sweeps with over-relaxation over the velocity faces.
+    #[default]
+    Sor,
+    /// Simultaneous updates of all cells, like the GPU gather and scatter passes.
+    Jacobi,
+    /// Gauss-Seidel with over-relaxation, updating the cells in a checkerboard order.
+    RedBlack,
+    /// Conjugate gradient with a MIC(0) preconditioner on the assembled Poisson system.
+    Pcg,
+    /// Geometric multigrid cycles with red-black Gauss-Seidel smoothing. Needs a handful of
+    /// cycles whatever the resolution, which makes it the solver of choice on large grids.
+    Multigrid(Cycle),
+}
+
+impl Solver {
+    pub fn build(self, config: &SimConfig) -> Box<dyn PressureSolver> {
+        match self {
+            Solver::Sor => Box::new(Sor::new(config.over_relaxation)),
+            Solver::Jacobi => Box::new(Jacobi::default()),
+            Solver::RedBlack => Box::new(RedBlack::new(config.over_relaxation)),
+            Solver::Pcg => Box::new(Pcg),
+            Solver::Multigrid(cycle) => Box::new(Multigrid::new(cycle)),
+        }
+    }
+}
+
+/// Makes the velocity on the faces of a MAC grid divergence free.
+pub trait PressureSolver: Send {
+    fn name(&self) -> &'static str;
+
+    /// Corrects `u` and `v` in place, stores the pressure in `p` and reports how far it got.
+    fn project(&mut self, projection: Projection) -> SolveReport;
+}
+
+/// One pressure projection: the fields to correct and the parameters to do it with.
+pub struct Projection<'a> {
+    pub u: &'a mut Field2,
+    pub v: &'a mut Field2,
+    /// Solid mask, 1.0 fluid, 0.0 solid.
+    pub s: &'a Field2,
+    /// Level set of a free surface, negative in the liquid. Pressure is only solved in the
+    /// liquid and vanishes in the air; `None` if all fluid cells are liquid.
+    pub phi: Option<&'a Field2>,
+    pub p: &'a mut Field2,
+    /// Cell size in meters.
+    pub size: f32,
+    pub dt: f32,
+    /// Fluid density in kg/m³.
+    pub density: f32,
+    /// Stop once the residual drops below this divergence in 1/s.
+    pub tolerance: Option<f32>,
+    pub residual_norm: ResidualNorm,
+    pub max_iterations: usize,
+}
+
+impl<'a> Projection<'a> {
+    /// Scale from a velocity potential to a pressure.
+    pub fn pressure_scale(&self) -> f32 {
+        self.density * self.size / self.dt
+    }
+
+    /// Max and root mean square divergence over the liquid cells, in 1/s.
+    pub fn residual(&self) -> (f32, f32) {
+        divergence(self.u, self.v, self.s, self.size, self.liquid())
+    }
+
+    /// Whether cell `k` is a liquid cell, ignoring solids.
+    pub(crate) fn liquid(&self) -> impl Fn(usize) -> bool + Sync + 'a {
+        let phi = self.phi.map(Field2::data);
+        move |k| phi.is_none_or(|phi| phi[k] < 0.0)
+    }
+
+    /// Measures the divergence left after a solve of `iterations`.
+    pub fn report(&self, iterations: usize) -> SolveReport {
+        let (max_residual, l2_residual) = self.residual();
+        let mut report = SolveReport {
+            iterations,
+            max_residual,
+            l2_residual,
+            converged: false,
+        };
+        report.converged = self
+            .tolerance
+            .is_some_and(|tolerance| report.residual(self.residual_norm) < tolerance);
+        report
+    }
+
+    /// Tolerance on the flux residual of the pressure system. Without a configured tolerance the
+    /// solve stops once the `initial` residual has dropped by [`RELATIVE_TOLERANCE`].
+    pub(crate) fn flux_tolerance(&self, initial: f64) -> f64 {
+        // The residual is a velocity flux, the tolerance a divergence.
+        match self.tolerance {
+            Some(tolerance) => tolerance as f64 * self.size as f64,
+            None => RELATIVE_TOLERANCE * initial,
+        }
+    }
+
+    /// Velocity flux out of every `unknown` cell, the right hand side `b` of the pressure system
+    /// `A x = b`.
+    pub(crate) fn flux_divergence(&self, unknown: impl Fn(usize) -> bool) -> Vec<f64> {
+        let (u, v, s) = (&self.u, &self.v, self.s);
+        let mut b = vec![0.0; s.data().len()];
+        for (i, j) in s.interior() {
+            let k = s.index(i, j);
+            if unknown(k) {
+                b[k] = (u[(i + 1, j)] - u[(i, j)] + v[(i, j + 1)] - v[(i, j)]) as f64;
+            }
+        }
+        b
+    }
+
+    /// Adds to each face between two `unknown` cells the difference of the potential `x` across
+    /// it, and stores `x` scaled to a pressure in `p`.
+    ///
+    /// Across faces between an unknown and an air cell the potential drops to zero, at the center
+    /// of the air cell or with `ghost_fluid` where the level set crosses the face. Non-solid cells
+    /// of the border are air at open sides of the domain.
+    pub(crate) fn apply_potential(
+        &mut self,
+        x: &[f64],
+        unknown: impl Fn(usize) -> bool,
+        ghost_fluid: bool,
+    ) {
+        let (row, height) = (self.p.nx(), self.p.ny() - 2);
+        let s = self.s;
+        let air = |k: usize| s.data()[k] != 0.0 && !unknown(k);
+        let phi = self.phi.filter(|_| ghost_fluid);
+        // The border of an open side keeps the potential at zero at its center
+        let weight = |k, n| match phi {
+            Some(phi) if !s.is_border(n) => surface_weight(phi, k, n),
+            _ => 1.0,
+        };
+        // Potential difference across the face from cell `a` to cell `b`
+        let gradient = |a: usize, b: usize| match (unknown(a), unknown(b)) {
+            (true, true) => x[b] - x[a],
+            (false, true) if air(a) => x[b] * weight(b, a),
+            (true, false) if air(b) => -x[a] * weight(a, b),
+            _ => 0.0,
+        };
+        // Faces of the interior cells, including those on the right and top of the domain
+        let width = row - 2;
+        for j in 1..=height + 1 {
+            for i in 1..=width + 1 {
+                let k = j * row + i;
+                if j <= height {
+                    self.u[(i, j)] += gradient(k - 1, k) as f32;
+                }
+                if i <= width {
+                    self.v[(i, j)] += gradient(k - row, k) as f32;
+                }
+            }
+        }
+
+        let scale = self.pressure_scale();
+        for (value, x) in self.p.data_mut().iter_mut().zip(x) {
+            *value = *x as f32 * scale;
+        }
+    }
+
+    /// Runs `sweep` until the residual drops below the tolerance, or `max_iterations` times.
+    pub(crate) fn iterate(&mut self, mut sweep: impl FnMut(&mut Projection)) -> SolveReport {
+        self.p.fill(0.0);
+
+        for iteration in 1..=self.max_iterations {
+            sweep(self);
+
+            if self.tolerance.is_some() {
+                let report = self.report(iteration);
+                if report.converged {
+                    return report;
+                }
+            }
+        }
+
+        self.report(self.max_iterations)
+    }
+}
+
+/// Norm of the divergence residual checked against [`SimConfig::tolerance`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResidualNorm {
+    /// Largest divergence of any fluid cell.
+    #[default]
+    Max,
+    /// Root mean square divergence over the fluid cells.
+    L2,
+}
+
+/// Outcome of one pressure projection.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SolveReport {
+    /// Sweeps, iterations or cycles performed, depending on the solver.
+    pub iterations: usize,
+    /// Largest remaining divergence in 1/s.
+    pub max_residual: f32,
+    /// Root mean square remaining divergence in 1/s.
+    pub l2_residual: f32,
+    /// Whether the solver stopped because the residual dropped below the tolerance.
+    pub converged: bool,
+}
+
+impl SolveReport {
+    pub fn residual(&self, norm: ResidualNorm) -> f32 {
+        match norm {
+            ResidualNorm::Max => self.max_residual,
+            ResidualNorm::L2 => self.l2_residual,
+        }
+    }
+}
+
+/// Max and root mean square velocity divergence over the fluid cells, in 1/s.
+pub fn residual(u: &Field2, v: &Field2, s: &Field2, size: f32) -> (f32, f32) {
+    divergence(u, v, s, size, |_| true)
+}
+
+/// Max and root mean square velocity divergence over the fluid cells `k` with `cell(k)`.
+fn divergence(
+    u: &Field2,
+    v: &Field2,
+    s: &Field2,
+    size: f32,
+    cell: impl Fn(usize) -> bool,
+) -> (f32, f32) {
+    let mut max: f32 = 0.0;
+    let mut sum = 0.0;
+    let mut count = 0;
+    for (i, j) in s.interior() {
+        if s[(i, j)] == 0.0 || !cell(s.index(i, j)) {
+            continue;
+        }
+        let div = (u[(i + 1, j)] - u[(i, j)] + v[(i, j + 1)] - v[(i, j)]) / size;
+        max = max.max(div.abs());
+        sum += div * div;
+        count += 1;
+    }
+    let l2 = if count == 0 {
+        0.0
+    } else {
+        (sum / count as f32).sqrt()
+    };
+    (max, l2)
+}
+
+/// Weight of the face between liquid cell `k` and air cell `n` in the pressure system with the
+/// ghost fluid method of Gibou et al.: the pressure vanishes where the level set `phi` crosses the
+/// face, so the weight is one over the fraction of the face that lies in the liquid.
+pub(crate) fn surface_weight(phi: &Field2, k: usize, n: usize) -> f64 {
+    let (inside, outside) = (phi.data()[k] as f64, phi.data()[n] as f64);
+    let fraction = inside / (inside - outside);
+    1.0 / fraction.max(MIN_LIQUID_FRACTION)
+}
+
+/// Root mean square or max of the entries of `r` that belong to `unknown` cells.
+pub(crate) fn norm(r: &[f64], unknown: impl Fn(usize) -> bool, norm: ResidualNorm) -> f64 {
+    let unknowns = r.iter().enumerate().filter(|&(k, _)| unknown(k));
+    match norm {
+        ResidualNorm::Max => unknowns.fold(0.0, |a, (_, r)| a.max(r.abs())),
+        ResidualNorm::L2 => {
+            let (sum, count) =
+                unknowns.fold((0.0, 0), |(sum, count), (_, r)| (sum + r * r, count + 1));
+            if count == 0 {
+                0.0
+            } else {
+                (sum / count as f64).sqrt()
+            }
+        }
+    }
+}
@@ -0,0 +1,437 @@
+//! Geometric multigrid for the pressure Poisson system on cell-centered grids.
+//!
+//! Coarse cells cover 2x2 fine cells and are fluid if any of them is. Residuals are restricted by
+//! summing the children, corrections prolongated by injection, and every level is smoothed with
+//! red-black Gauss-Seidel. The coarsest level is solved with PCG, since on thin domains it still
+//! has many cells along the long side.
+//!
+//! Air cells of a free surface and the ring at open sides of the domain are Dirichlet boundaries
+//! with zero pressure. On the finest level the zero pressure lies at the surface itself, placed
+//! with the ghost fluid method like in PCG, and on all levels the zero pressure of open sides stays
+//! at the center of the finest ring. Coarse cells are only liquid if all their fluid children are,
+//! which keeps the coarse surface inside the liquid.
+
+use serde::Deserialize;
+
+use crate::field::Field2;
+use crate::pressure::pcg::{self, Matrix5};
+use crate::pressure::{self, PressureSolver, Projection, ResidualNorm, SolveReport};
+
+/// Red-black Gauss-Seidel sweeps before and after the coarse grid correction.
+const SMOOTHING_SWEEPS: usize = 2;
+/// Residual reduction of the PCG solve of the coarsest level.
+const COARSE_TOLERANCE: f64 = 1e-3;
+/// Levels are coarsened until one side has at most this many cells.
+const COARSEST_SIZE: usize = 4;
+
+/// Recursion pattern of a multigrid cycle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cycle {
+    /// One coarse grid correction per level.
+    #[default]
+    V,
+    /// Two coarse grid corrections per level, more robust on complicated masks.
+    W,
+}
+
+impl Cycle {
+    fn corrections(self) -> usize {
+        match self {
+            Cycle::V => 1,
+            Cycle::W => 2,
+        }
+    }
+}
+
+/// The Poisson system on one level of the hierarchy, including a ring of cells that are solid
+/// except at open sides.
+struct Level {
+    width: usize,
+    height: usize,
+    /// Cells of the finest level along each side of a cell.
+    span: usize,
+    /// Size of the finest level.
+    finest: (usize, usize),
+    /// Cells that are not solid.
+    fluid: Vec<bool>,
+    unknown: Vec<bool>,
+    /// Number of non-solid neighbours of each unknown, with the open sides weighted by
+    /// [`ring_weight`] and air neighbours by [`surface_weight`](pressure::surface_weight) on the
+    /// finest level.
+    diag: Vec<f64>,
+    /// Connected regions of unknowns without a Dirichlet neighbour, where the pressure is only
+    /// defined up to a constant.
+    closed: Vec<Vec<usize>>,
+    x: Vec<f64>,
+    b: Vec<f64>,
+    r: Vec<f64>,
+}
+
+impl Level {
+    /// A level of `width` x `height` cells, each covering `span` x `span` cells of a finest level
+    /// of `finest` cells, where `fluid(i, j)` tells which cells are not solid and `liquid(i, j)`
+    /// which of those are unknowns rather than air.
+    fn new(
+        (width, height): (usize, usize),
+        span: usize,
+        finest: (usize, usize),
+        fluid: impl Fn(usize, usize) -> bool,
+        liquid: impl Fn(usize, usize) -> bool,
+    ) -> Level {
+        let row = width + 2;
+        let n = row * (height + 2);
+        let mut is_fluid = vec![false; n];
+        let mut unknown = vec![false; n];
+        let mut diag = vec![0.0; n];
+        // The ring is only fluid at open sides of the domain, where the pressure is zero
+        for j in 0..height + 2 {
+            for i in [0, width + 1] {
+                is_fluid[j * row + i] = fluid(i, j);
+            }
+        }
+        for i in 1..=width {
+            for j in [0, height + 1] {
+                is_fluid[j * row + i] = fluid(i, j);
+            }
+        }
+        for j in 1..=height {
+            for i in 1..=width {
+                if !fluid(i, j) {
+                    continue;
+                }
+                let neighbours = [(i - 1, j), (i + 1, j), (i, j - 1), (i, j + 1)];
+                let count = neighbours.iter().filter(|&&(i, j)| fluid(i, j)).count();
+                let weight = |&(ni, nj): &(usize, usize)| match (ni, nj) {
+                    _ if !fluid(ni, nj) => 0.0,
+                    (0, _) => ring_weight(i, span, finest.0, false),
+                    (_, 0) => ring_weight(j, span, finest.1, false),
+                    _ if ni == width + 1 => ring_weight(i, span, finest.0, true),
+                    _ if nj == height + 1 => ring_weight(j, span, finest.1, true),
+                    _ => 1.0,
+                };
+                let k = j * row + i;
+                is_fluid[k] = count > 0;
+                unknown[k] = count > 0 && liquid(i, j);
+                diag[k] = neighbours.iter().map(weight).sum();
+            }
+        }
+
+        let closed = closed_regions(row, &is_fluid, &unknown);
+        Level {
+            width,
+            height,
+            span,
+            finest,
+            fluid: is_fluid,
+            unknown,
+            diag,
+            closed,
+            x: vec![0.0; n],
+            b: vec![0.0; n],
+            r: vec![0.0; n],
+        }
+    }
+
+    fn row(&self) -> usize {
+        self.width + 2
+    }
+
+    /// Moves the zero pressure of the air neighbours of the unknowns from their centers to the
+    /// surface where the level set `phi` crosses zero.
+    fn place_surface(&mut self, phi: &Field2) {
+        let row = self.row();
+        for k in (0..self.unknown.len()).filter(|&k| self.unknown[k]) {
+            for n in [k - 1, k + 1, k - row, k + row] {
+                let air = self.fluid[n] && !self.unknown[n] && !phi.is_border(n);
+                if air {
+                    self.diag[k] += pressure::surface_weight(phi, k, n) - 1.0;
+                }
+            }
+        }
+    }
+
+    /// The next level, or `None` if this one is small enough to be solved directly.
+    fn coarsen(&self) -> Option<Level> {
+        if self.width.min(self.height) <= COARSEST_SIZE {
+            return None;
+        }
+        let size = (self.width.div_ceil(2), self.height.div_ceil(2));
+        let fluid = |i, j| self.children(size, i, j).any(|k| self.fluid[k]);
+        let liquid = |i, j| {
+            self.children(size, i, j)
+                .all(|k| self.unknown[k] || !self.fluid[k])
+        };
+        Some(Level::new(size, 2 * self.span, self.finest, fluid, liquid))
+    }
+
+    /// Cells of this level covered by cell `(i, j)` of a coarser level of `width` x `height`
+    /// cells. The rings of the two levels only cover each other.
+    fn children(
+        &self,
+        (width, height): (usize, usize),
+        i: usize,
+        j: usize,
+    ) -> impl Iterator<Item = usize> + '_ {
+        let span = |c: usize, coarse: usize, fine: usize| match c {
+            0 => 0..=0,
+            _ if c == coarse + 1 => fine + 1..=fine + 1,
+            _ => 2 * c - 1..=(2 * c).min(fine),
+        };
+        let row = self.row();
+        let columns = span(i, width, self.width);
+        span(j, height, self.height)
+            .flat_map(move |fj| columns.clone().map(move |fi| fj * row + fi))
+    }
+
+    /// Sum of `x` over the unknown neighbours of `k`.
+    fn neighbour_sum(&self, k: usize) -> f64 {
+        let row = self.row();
+        [k - 1, k + 1, k - row, k + row]
+            .iter()
+            .filter(|&&n| self.unknown[n])
+            .map(|&n| self.x[n])
+            .sum()
+    }
+
+    fn smooth(&mut self, sweeps: usize) {
+        let row = self.row();
+        for _ in 0..sweeps {
+            for color in 0..2 {
+                for j in 1..=self.height {
+                    let start = 1 + (j + 1 + color) % 2;
+                    for i in (start..=self.width).step_by(2) {
+                        let k = j * row + i;
+                        if self.unknown[k] {
+                            self.x[k] = (self.b[k] + self.neighbour_sum(k)) / self.diag[k];
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The system of this level as a matrix.
+    fn matrix(&self) -> Matrix5 {
+        let diag: Vec<_> = (self.diag.iter().zip(&self.unknown))
+            .map(|(&diag, &unknown)| if unknown { diag } else { 0.0 })
+            .collect();
+        Matrix5::stencil(self.row(), &diag, &self.unknown)
+    }
+
+    /// Reduces the residual by [`COARSE_TOLERANCE`] with PCG on the system `matrix` of this
+    /// level.
+    fn solve(&mut self, matrix: &Matrix5) {
+        self.update_residual();
+        remove_means(&self.closed, &mut self.r);
+        let norm = ResidualNorm::Max;
+        let tolerance = COARSE_TOLERANCE * pressure::norm(&self.r, |k| self.unknown[k], norm);
+        let count = self.unknown.iter().filter(|&&unknown| unknown).count();
+        let mut correction = vec![0.0; self.x.len()];
+        pcg::solve(matrix, &self.r, &mut correction, tolerance, norm, count);
+        for (x, correction) in self.x.iter_mut().zip(correction) {
+            *x += correction;
+        }
+    }
+
+    /// `r = b - A x`
+    fn update_residual(&mut self) {
+        for k in 0..self.x.len() {
+            self.r[k] = if self.unknown[k] {
+                self.b[k] - self.diag[k] * self.x[k] + self.neighbour_sum(k)
+            } else {
+                0.0
+            };
+        }
+    }
+
+    /// Sets the right hand side of `coarse` to the restricted residual and clears its solution.
+    fn restrict(&self, coarse: &mut Level) {
+        let (size, coarse_row) = ((coarse.width, coarse.height), coarse.row());
+        for j in 1..=coarse.height {
+            for i in 1..=coarse.width {
+                let sum = self.children(size, i, j).map(|k| self.r[k]).sum();
+                coarse.b[j * coarse_row + i] = sum;
+            }
+        }
+        coarse.x.fill(0.0);
+    }
+
+    /// Adds the correction of `coarse` to every unknown.
+    fn prolongate(&mut self, coarse: &Level) {
+        let (row, coarse_row) = (self.row(), coarse.row());
+        for j in 1..=self.height {
+            for i in 1..=self.width {
+                let k = j * row + i;
+                if self.unknown[k] {
+                    self.x[k] += coarse.x[j.div_ceil(2) * coarse_row + i.div_ceil(2)];
+                }
+            }
+        }
+    }
+}
+
+/// A multigrid hierarchy for one solid mask.
+struct Hierarchy {
+    levels: Vec<Level>,
+    /// The system of the coarsest level.
+    coarsest: Matrix5,
+}
+
+impl Hierarchy {
+    /// The hierarchy of the fluid cells of `s`, with the pressure only solved in the liquid
+    /// below the level set `phi`.
+    fn new(s: &Field2, phi: Option<&Field2>) -> Hierarchy {
+        let (width, height) = (s.nx() - 2, s.ny() - 2);
+        let fluid = |i, j| s[(i, j)] != 0.0;
+        let liquid = |i, j| phi.is_none_or(|phi| phi[(i, j)] < 0.0);
+        let size = (width, height);
+        let mut fine = Level::new(size, 1, size, fluid, liquid);
+        if let Some(phi) = phi {
+            fine.place_surface(phi);
+        }
+        let mut levels = vec![fine];
+        while let Some(coarse) = levels.last().unwrap().coarsen() {
+            levels.push(coarse);
+        }
+        let coarsest = levels.last().unwrap().matrix();
+        Hierarchy { levels, coarsest }
+    }
+
+    fn cycle(levels: &mut [Level], coarsest: &Matrix5, cycle: Cycle) {
+        let (fine, coarser) = levels.split_first_mut().unwrap();
+        if coarser.is_empty() {
+            fine.solve(coarsest);
+            return;
+        }
+
+        fine.smooth(SMOOTHING_SWEEPS);
+        fine.update_residual();
+        fine.restrict(&mut coarser[0]);
+        for _ in 0..cycle.corrections() {
+            Self::cycle(coarser, coarsest, cycle);
+        }
+        fine.prolongate(&coarser[0]);
+        fine.smooth(SMOOTHING_SWEEPS);
+    }
+
+    /// Cycles until the `norm` of the residual drops below `tolerance` or after `max_cycles`.
+    /// Returns the cycles used.
+    fn solve(
+        &mut self,
+        cycle: Cycle,
+        tolerance: f64,
+        norm: ResidualNorm,
+        max_cycles: usize,
+    ) -> usize {
+        let fine = &mut self.levels[0];
+        remove_means(&fine.closed, &mut fine.b);
+        fine.x.fill(0.0);
+        fine.r.copy_from_slice(&fine.b);
+
+        for iteration in 0..max_cycles {
+            let fine = &self.levels[0];
+            if pressure::norm(&fine.r, |k| fine.unknown[k], norm) <= tolerance {
+                return iteration;
+            }
+            Self::cycle(&mut self.levels, &self.coarsest, cycle);
+            self.levels[0].update_residual();
+        }
+        max_cycles
+    }
+}
+
+/// Weight of the open side at the low or `high` end of an axis of `finest` cells in the row of
+/// cell `c` next to it, on a level whose cells span `span` finest cells. The zero pressure lies at
+/// the center of the finest ring, which coarse cells are less than one of their cells away from.
+fn ring_weight(c: usize, span: usize, finest: usize, high: bool) -> f64 {
+    let first = (c - 1) * span + 1;
+    let last = (c * span).min(finest);
+    let center = (first + last) as f64 / 2.0;
+    let distance = if high {
+        (finest + 1) as f64 - center
+    } else {
+        center
+    };
+    span as f64 / distance
+}
+
+/// The connected regions of `unknown` cells on a grid with `row` cells per row that border no
+/// `fluid` cell other than unknowns. Walls all around leave the pressure of such a region only
+/// defined up to a constant, so the right hand side must not have a component along it.
+fn closed_regions(row: usize, fluid: &[bool], unknown: &[bool]) -> Vec<Vec<usize>> {
+    let mut visited = vec![false; unknown.len()];
+    let mut regions = Vec::new();
+    for start in 0..unknown.len() {
+        if !unknown[start] || visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let (mut region, mut stack, mut closed) = (Vec::new(), vec![start], true);
+        while let Some(k) = stack.pop() {
+            region.push(k);
+            for n in [k - 1, k + 1, k - row, k + row] {
+                if !unknown[n] {
+                    closed &= !fluid[n];
+                } else if !visited[n] {
+                    visited[n] = true;
+                    stack.push(n);
+                }
+            }
+        }
+        if closed {
+            regions.push(region);
+        }
+    }
+    regions
+}
+
+/// Removes the mean of `b` over each of the closed `regions`, without which the system has no
+/// solution.
+fn remove_means(regions: &[Vec<usize>], b: &mut [f64]) {
+    for region in regions {
+        let mean = region.iter().map(|&k| b[k]).sum::<f64>() / region.len() as f64;
+        for &k in region {
+            b[k] -= mean;
+        }
+    }
+}
+
+/// Geometric multigrid for the pressure Poisson system.
+///
+/// Without a tolerance the solve stops once the residual has dropped by
+/// [`RELATIVE_TOLERANCE`](crate::pressure::RELATIVE_TOLERANCE).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Multigrid {
+    cycle: Cycle,
+}
+
+impl Multigrid {
+    pub fn new(cycle: Cycle) -> Multigrid {
+        Multigrid { cycle }
+    }
+}
+
+impl PressureSolver for Multigrid {
+    fn name(&self) -> &'static str {
+        match self.cycle {
+            Cycle::V => "Multigrid V-cycle",
+            Cycle::W => "Multigrid W-cycle",
+        }
+    }
+
+    fn project(&mut self, mut projection: Projection) -> SolveReport {
+        let mut hierarchy = Hierarchy::new(projection.s, projection.phi);
+        let fine = &mut hierarchy.levels[0];
+        let unknown = fine.unknown.clone();
+
+        fine.b = projection.flux_divergence(|k| unknown[k]);
+        let norm = projection.residual_norm;
+        let tolerance = projection.flux_tolerance(pressure::norm(&fine.b, |k| unknown[k], norm));
+        let max_cycles = projection.max_iterations;
+        let iterations = hierarchy.solve(self.cycle, tolerance, norm, max_cycles);
+
+        projection.apply_potential(&hierarchy.levels[0].x, |k| unknown[k], true);
+        projection.report(iterations)
+    }
+}
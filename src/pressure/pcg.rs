@@ -0,0 +1,322 @@
+//! Conjugate gradient with a modified incomplete Cholesky (MIC(0)) preconditioner,
+//! following Bridson, "Fluid Simulation for Computer Graphics", chapter 5.
+
+use crate::diffusion::Condition;
+use crate::field::Field2;
+use crate::pressure::{self, PressureSolver, Projection, ResidualNorm, SolveReport};
+
+/// Tuning of the modified incomplete Cholesky factorization.
+const TAU: f64 = 0.97;
+const SIGMA: f64 = 0.25;
+
+/// A symmetric matrix coupling each cell with its four neighbours, stored per cell as the diagonal
+/// and the coefficients towards the right (`plus_i`) and top (`plus_j`) neighbour.
+///
+/// Cells with a zero diagonal are not unknowns of the system.
+#[derive(Clone, Debug)]
+pub struct Matrix5 {
+    row: usize,
+    diag: Vec<f64>,
+    plus_i: Vec<f64>,
+    plus_j: Vec<f64>,
+    /// Whether constant vectors are in the null space, i.e. no cell has a Dirichlet neighbour.
+    singular: bool,
+}
+
+impl Matrix5 {
+    /// The pressure Poisson matrix of the fluid cells of `s`, with solid walls as Neumann boundaries
+    /// and the non-solid border of open sides as Dirichlet boundaries.
+    ///
+    /// Solving `A x = div` for the velocity divergence `div` yields a potential `x` whose
+    /// differences across fluid faces remove the divergence. With a level set `phi` only the liquid
+    /// cells are unknowns, and the potential vanishes at the liquid surface.
+    pub fn poisson(s: &Field2, phi: Option<&Field2>) -> Matrix5 {
+        let row = s.nx();
+        let n = s.data().len();
+        let mut matrix = Matrix5 {
+            row,
+            diag: vec![0.0; n],
+            plus_i: vec![0.0; n],
+            plus_j: vec![0.0; n],
+            singular: true,
+        };
+
+        let liquid = |k: usize| phi.is_none_or(|phi| phi.data()[k] < 0.0);
+        let mut unknown = vec![false; n];
+        for (i, j) in s.interior() {
+            let k = s.index(i, j);
+            unknown[k] = s.data()[k] != 0.0 && liquid(k);
+        }
+        let unknown = |k: usize| unknown[k];
+        for (i, j) in s.interior() {
+            let k = s.index(i, j);
+            if !unknown(k) {
+                continue;
+            }
+            for n in [k - 1, k + 1, k - row, k + row] {
+                if unknown(n) {
+                    matrix.diag[k] += 1.0;
+                } else if s.data()[n] != 0.0 {
+                    // Cells of the border that are not solid belong to open sides with zero
+                    // pressure
+                    matrix.diag[k] += if s.is_border(n) {
+                        1.0
+                    } else {
+                        let phi = phi.expect("Air cells only exist with a level set");
+                        pressure::surface_weight(phi, k, n)
+                    };
+                    matrix.singular = false;
+                }
+            }
+            if unknown(k + 1) {
+                matrix.plus_i[k] = -1.0;
+            }
+            if unknown(k + row) {
+                matrix.plus_j[k] = -1.0;
+            }
+        }
+
+        matrix
+    }
+
+    /// The matrix of a five point stencil on a field with `row` samples per row, with `diag` on the
+    /// diagonal of the `unknown` samples and -1 between neighbouring unknowns. Unknowns whose
+    /// diagonal exceeds their number of unknown neighbours border a Dirichlet boundary.
+    pub(crate) fn stencil(row: usize, diag: &[f64], unknown: &[bool]) -> Matrix5 {
+        let n = unknown.len();
+        let mut matrix = Matrix5 {
+            row,
+            diag: vec![0.0; n],
+            plus_i: vec![0.0; n],
+            plus_j: vec![0.0; n],
+            singular: true,
+        };
+
+        for k in (0..n).filter(|&k| unknown[k]) {
+            let neighbours = [k - 1, k + 1, k - row, k + row];
+            let coupled = neighbours.iter().filter(|&&n| unknown[n]).count();
+            matrix.diag[k] = diag[k];
+            if diag[k] > coupled as f64 {
+                matrix.singular = false;
+            }
+            if unknown[k + 1] {
+                matrix.plus_i[k] = -1.0;
+            }
+            if unknown[k + row] {
+                matrix.plus_j[k] = -1.0;
+            }
+        }
+
+        matrix
+    }
+
+    /// The matrix of one backward Euler diffusion step `(I - a ∇²) x = b` on the `unknown`
+    /// samples of a field with `row` samples per row, where `a = k dt / h²`.
+    ///
+    /// Samples that are not unknowns are either fixed values the caller moves into `b`
+    /// (`Dirichlet`) or nothing diffuses across them (`Neumann`).
+    pub fn diffusion(unknown: &[bool], row: usize, a: f64, condition: Condition) -> Matrix5 {
+        let n = unknown.len();
+        let mut matrix = Matrix5 {
+            row,
+            diag: vec![0.0; n],
+            plus_i: vec![0.0; n],
+            plus_j: vec![0.0; n],
+            singular: false,
+        };
+
+        for k in (0..n).filter(|&k| unknown[k]) {
+            let neighbours = [k - 1, k + 1, k - row, k + row];
+            let coupled = match condition {
+                Condition::Dirichlet => neighbours.len(),
+                Condition::Neumann => neighbours.iter().filter(|&&n| unknown[n]).count(),
+            };
+            matrix.diag[k] = 1.0 + a * coupled as f64;
+            if unknown[k + 1] {
+                matrix.plus_i[k] = -a;
+            }
+            if unknown[k + row] {
+                matrix.plus_j[k] = -a;
+            }
+        }
+
+        matrix
+    }
+
+    fn is_unknown(&self, k: usize) -> bool {
+        self.diag[k] != 0.0
+    }
+
+    /// `out = A x`
+    fn apply(&self, x: &[f64], out: &mut [f64]) {
+        let row = self.row;
+        for k in 0..x.len() {
+            if !self.is_unknown(k) {
+                out[k] = 0.0;
+                continue;
+            }
+            out[k] = self.diag[k] * x[k]
+                + self.plus_i[k] * x[k + 1]
+                + self.plus_i[k - 1] * x[k - 1]
+                + self.plus_j[k] * x[k + row]
+                + self.plus_j[k - row] * x[k - row];
+        }
+    }
+
+    fn precondition(&self) -> Vec<f64> {
+        let row = self.row;
+        let mut precon = vec![0.0; self.diag.len()];
+        for k in 0..precon.len() {
+            if !self.is_unknown(k) {
+                continue;
+            }
+            let (ai, aj) = (self.plus_i[k - 1], self.plus_j[k - row]);
+            let (pi, pj) = (precon[k - 1], precon[k - row]);
+            let mut e = self.diag[k]
+                - (ai * pi).powi(2)
+                - (aj * pj).powi(2)
+                - TAU * (ai * self.plus_j[k - 1] * pi * pi + aj * self.plus_i[k - row] * pj * pj);
+            if e < SIGMA * self.diag[k] {
+                e = self.diag[k];
+            }
+            precon[k] = 1.0 / e.sqrt();
+        }
+        precon
+    }
+
+    /// `z = M^-1 r` by a forward and a backward substitution with the factor `L`.
+    fn apply_preconditioner(&self, precon: &[f64], r: &[f64], q: &mut [f64], z: &mut [f64]) {
+        let row = self.row;
+        for k in 0..r.len() {
+            if !self.is_unknown(k) {
+                q[k] = 0.0;
+                continue;
+            }
+            let t = r[k]
+                - self.plus_i[k - 1] * precon[k - 1] * q[k - 1]
+                - self.plus_j[k - row] * precon[k - row] * q[k - row];
+            q[k] = t * precon[k];
+        }
+        for k in (0..r.len()).rev() {
+            if !self.is_unknown(k) {
+                z[k] = 0.0;
+                continue;
+            }
+            let t = q[k]
+                - self.plus_i[k] * precon[k] * z[k + 1]
+                - self.plus_j[k] * precon[k] * z[k + row];
+            z[k] = t * precon[k];
+        }
+    }
+
+    /// Max or root mean square of `r` over the unknowns.
+    fn norm(&self, r: &[f64], norm: ResidualNorm) -> f64 {
+        pressure::norm(r, |k| self.is_unknown(k), norm)
+    }
+
+    fn remove_mean(&self, x: &mut [f64]) {
+        let (sum, count) = x
+            .iter()
+            .enumerate()
+            .filter(|&(k, _)| self.is_unknown(k))
+            .fold((0.0, 0), |(sum, count), (_, x)| (sum + x, count + 1));
+        if count == 0 {
+            return;
+        }
+        let mean = sum / count as f64;
+        for k in 0..x.len() {
+            if self.is_unknown(k) {
+                x[k] -= mean;
+            }
+        }
+    }
+}
+
+/// Solves `A x = b` starting from `x = 0`, until the `norm` of the residual drops below `tolerance`
+/// or after `max_iterations`. Returns the iterations used and the final residual.
+pub fn solve(
+    matrix: &Matrix5,
+    b: &[f64],
+    x: &mut [f64],
+    tolerance: f64,
+    norm: ResidualNorm,
+    max_iterations: usize,
+) -> (usize, f64) {
+    let n = b.len();
+    x.fill(0.0);
+
+    let mut r = b.to_vec();
+    if matrix.singular {
+        // The system is only solvable if `b` is orthogonal to the null space.
+        matrix.remove_mean(&mut r);
+    }
+    let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>();
+
+    let mut residual = matrix.norm(&r, norm);
+    if residual <= tolerance {
+        return (0, residual);
+    }
+
+    let precon = matrix.precondition();
+    let mut q = vec![0.0; n];
+    let mut z = vec![0.0; n];
+    matrix.apply_preconditioner(&precon, &r, &mut q, &mut z);
+    let mut search = z.clone();
+    let mut sigma = dot(&z, &r);
+
+    for iteration in 1..=max_iterations {
+        matrix.apply(&search, &mut z);
+        let alpha = sigma / dot(&z, &search);
+        for k in 0..n {
+            x[k] += alpha * search[k];
+            r[k] -= alpha * z[k];
+        }
+
+        residual = matrix.norm(&r, norm);
+        if residual <= tolerance {
+            return (iteration, residual);
+        }
+
+        matrix.apply_preconditioner(&precon, &r, &mut q, &mut z);
+        let sigma_new = dot(&z, &r);
+        if sigma_new == 0.0 {
+            return (iteration, residual);
+        }
+        let beta = sigma_new / sigma;
+        for k in 0..n {
+            search[k] = z[k] + beta * search[k];
+        }
+        sigma = sigma_new;
+    }
+
+    (max_iterations, residual)
+}
+
+/// Conjugate gradient with a MIC(0) preconditioner on the pressure Poisson system. A free surface
+/// is placed with the ghost fluid method.
+///
+/// Without a tolerance the solve stops once the residual has dropped by
+/// [`RELATIVE_TOLERANCE`](crate::pressure::RELATIVE_TOLERANCE).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Pcg;
+
+impl PressureSolver for Pcg {
+    fn name(&self) -> &'static str {
+        "PCG"
+    }
+
+    fn project(&mut self, mut projection: Projection) -> SolveReport {
+        let matrix = Matrix5::poisson(projection.s, projection.phi);
+        let unknown = |k| matrix.is_unknown(k);
+
+        let b = projection.flux_divergence(unknown);
+        let norm = projection.residual_norm;
+        let tolerance = projection.flux_tolerance(matrix.norm(&b, norm));
+        let mut x = vec![0.0; b.len()];
+        let max_iterations = projection.max_iterations;
+        let (iterations, _) = solve(&matrix, &b, &mut x, tolerance, norm, max_iterations);
+
+        projection.apply_potential(&x, unknown, true);
+        projection.report(iterations)
+    }
+}
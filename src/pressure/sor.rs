@@ -0,0 +1,160 @@
+//! Gauss-Seidel relaxation with over-relaxation, operating directly on the velocity faces.
+//!
+//! Air cells of a free surface are never relaxed, which keeps their pressure at zero.
+
+use rayon::prelude::*;
+
+use crate::pressure::{PressureSolver, Projection, SolveReport};
+
+/// Gauss-Seidel sweeps over the cells in memory order.
+#[derive(Clone, Copy, Debug)]
+pub struct Sor {
+    over_relaxation: f32,
+}
+
+impl Sor {
+    pub fn new(over_relaxation: f32) -> Sor {
+        Sor { over_relaxation }
+    }
+}
+
+impl PressureSolver for Sor {
+    fn name(&self) -> &'static str {
+        "SOR"
+    }
+
+    fn project(&mut self, mut projection: Projection) -> SolveReport {
+        let over_relaxation = self.over_relaxation;
+        let liquid = projection.liquid();
+        projection.iterate(|projection| {
+            let scale = projection.pressure_scale();
+            let cells = Cells::new(projection);
+            for j in 1..=cells.height {
+                for i in 1..=cells.width {
+                    let k = j * cells.row + i;
+                    if liquid(k) {
+                        cells.relax(projection, k, over_relaxation, scale);
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Gauss-Seidel sweeps updating first the cells with even `i + j`, then the odd ones.
+///
+/// Cells of one color share no faces, so all cells of a color are relaxed in parallel, row by
+/// row, with the same result as a serial sweep.
+#[derive(Clone, Debug)]
+pub struct RedBlack {
+    over_relaxation: f32,
+    /// Divergence of each cell of the current color, spread over its non-solid neighbours.
+    avg_div: Vec<f32>,
+}
+
+impl RedBlack {
+    pub fn new(over_relaxation: f32) -> RedBlack {
+        RedBlack {
+            over_relaxation,
+            avg_div: Vec::new(),
+        }
+    }
+}
+
+impl PressureSolver for RedBlack {
+    fn name(&self) -> &'static str {
+        "Red-black SOR"
+    }
+
+    fn project(&mut self, mut projection: Projection) -> SolveReport {
+        let over_relaxation = self.over_relaxation;
+        let avg_div = &mut self.avg_div;
+        avg_div.clear();
+        avg_div.resize(projection.s.data().len(), 0.0);
+        let liquid = projection.liquid();
+
+        projection.iterate(|projection| {
+            let scale = projection.pressure_scale();
+            let s = projection.s.data();
+            let row = projection.s.nx();
+            let height = projection.s.ny() - 2;
+
+            for color in 0..2 {
+                let (u, v) = (projection.u.data(), projection.v.data());
+                avg_div
+                    .par_chunks_mut(row)
+                    .enumerate()
+                    .for_each(|(j, avg_div)| {
+                        avg_div.fill(0.0);
+                        if j == 0 || j > height {
+                            return;
+                        }
+                        let start = 1 + (j + 1 + color) % 2;
+                        for i in (start..row - 1).step_by(2) {
+                            let k = j * row + i;
+                            let ss = s[k - 1] + s[k - row] + s[k + 1] + s[k + row];
+                            if s[k] != 0.0 && ss != 0.0 && liquid(k) {
+                                let d = u[k + 1] - u[k] + v[k + row] - v[k];
+                                avg_div[i] = over_relaxation * d / ss;
+                            }
+                        }
+                    });
+
+                // Every face has at most one neighbour of the current color.
+                let avg_div = &*avg_div;
+                let u = projection.u.par_rows_mut();
+                let v = projection.v.par_rows_mut();
+                let p = projection.p.par_rows_mut();
+                u.zip(v)
+                    .zip(p)
+                    .skip(1)
+                    .for_each(|(((j, u), (_, v)), (_, p))| {
+                        for i in 1..row {
+                            let k = j * row + i;
+                            u[i] += avg_div[k] * s[k - 1] - avg_div[k - 1] * s[k];
+                            v[i] += avg_div[k] * s[k - row] - avg_div[k - row] * s[k];
+                            p[i] += avg_div[k] * scale;
+                        }
+                    });
+            }
+        })
+    }
+}
+
+/// Dimensions of the interior of a projection.
+struct Cells {
+    row: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Cells {
+    fn new(projection: &Projection) -> Cells {
+        let s = projection.s;
+        Cells {
+            row: s.nx(),
+            width: s.nx() - 2,
+            height: s.ny() - 2,
+        }
+    }
+
+    /// Removes the divergence of fluid cell `k` by adjusting its non-solid faces, accumulating
+    /// the pressure. All fields share the same layout, so neighbours are at fixed offsets of `k`.
+    #[inline]
+    fn relax(&self, projection: &mut Projection, k: usize, over_relaxation: f32, scale: f32) {
+        let row = self.row;
+        let s = projection.s.data();
+        let ss = s[k - 1] + s[k - row] + s[k + 1] + s[k + row];
+        if s[k] == 0.0 || ss == 0.0 {
+            return;
+        }
+        let u = projection.u.data_mut();
+        let v = projection.v.data_mut();
+        let d = over_relaxation * (u[k + 1] - u[k] + v[k + row] - v[k]);
+        u[k] += d * s[k - 1] / ss;
+        u[k + 1] -= d * s[k + 1] / ss;
+        v[k] += d * s[k - row] / ss;
+        v[k + row] -= d * s[k + row] / ss;
+        projection.p.data_mut()[k] += d / ss * scale;
+    }
+}
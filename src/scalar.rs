@@ -0,0 +1,127 @@
+//! Passive scalars: quantities like dye that are carried by the flow without acting on it.
+
+use macroquad::prelude::*;
+
+use crate::config::{Disk, Side};
+use crate::mask::{Marking, Mask};
+
+/// A passive scalar field of a [`Grid`](crate::Grid), e.g. one color of dye.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scalar {
+    /// Unique name to look the field up by.
+    pub name: String,
+    /// Diffusion coefficient in m²/s, 0 to only advect the scalar.
+    pub diffusion: f32,
+    /// Color of a concentration of 1 when rendering.
+    pub color: Color,
+    pub emitters: Vec<Emitter>,
+    /// Values the field starts with inside their regions, e.g. a blob of dye, and 0 elsewhere.
+    pub initial: Vec<Emitter>,
+}
+
+impl Scalar {
+    /// A scalar without diffusion or emitters.
+    pub fn new(name: impl Into<String>, color: Color) -> Scalar {
+        Scalar {
+            name: name.into(),
+            diffusion: 0.0,
+            color,
+            emitters: Vec::new(),
+            initial: Vec::new(),
+        }
+    }
+
+    pub fn with_diffusion(mut self, diffusion: f32) -> Scalar {
+        self.diffusion = diffusion;
+        self
+    }
+
+    pub fn with_emitter(mut self, region: impl Into<Region>, value: f32) -> Scalar {
+        self.emitters.push(Emitter {
+            region: region.into(),
+            value,
+        });
+        self
+    }
+
+    pub fn with_initial(mut self, region: impl Into<Region>, value: f32) -> Scalar {
+        self.initial.push(Emitter {
+            region: region.into(),
+            value,
+        });
+        self
+    }
+}
+
+/// Holds a scalar at `value` within the fluid cells of `region` at every step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Emitter {
+    pub region: Region,
+    pub value: f32,
+}
+
+/// Cells covered by an [`Emitter`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    Disk(Disk),
+    /// The row or column of cells along a wall, e.g. a heated floor.
+    Wall(Side),
+    /// The dye cells of [`SimConfig::mask`](crate::SimConfig::mask).
+    Mask,
+}
+
+impl Region {
+    /// Whether cell `(i, j)` of a grid of `width` x `height` cells with the scene `mask` lies
+    /// within the region.
+    pub fn contains(
+        &self,
+        i: usize,
+        j: usize,
+        width: usize,
+        height: usize,
+        mask: Option<&Mask>,
+    ) -> bool {
+        match self {
+            Region::Disk(disk) => {
+                let (center, radius) = disk.in_cells(width, height);
+                vec2(i as f32, j as f32).distance_squared(center) < radius * radius
+            }
+            Region::Wall(Side::Left) => i == 1,
+            Region::Wall(Side::Right) => i == width,
+            Region::Wall(Side::Bottom) => j == 1,
+            Region::Wall(Side::Top) => j == height,
+            Region::Mask => mask.is_some_and(|mask| mask.at(i, j, width, height) == Marking::Dye),
+        }
+    }
+}
+
+impl Region {
+    /// Whether a disk lies within the unit square; walls and the mask always do.
+    pub(crate) fn is_valid(&self) -> bool {
+        match self {
+            Region::Disk(disk) => disk.is_valid(),
+            Region::Wall(_) | Region::Mask => true,
+        }
+    }
+}
+
+impl From<Disk> for Region {
+    fn from(disk: Disk) -> Region {
+        Region::Disk(disk)
+    }
+}
+
+impl From<Side> for Region {
+    fn from(side: Side) -> Region {
+        Region::Wall(side)
+    }
+}
+
+/// Pseudo-random number in `[0, 1)` for the `n`-th draw, so seeding is reproducible.
+pub(crate) fn jitter(n: usize) -> f32 {
+    let mut h = (n as u32).wrapping_mul(0x9E37_79B9);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 13;
+    (h >> 8) as f32 / (1u32 << 24) as f32
+}
@@ -0,0 +1,563 @@
+//! Scenes described in TOML files: the size of the domain, its geometry, what is released into
+//! it and the settings of the solver, in one place for both the CPU and the GPU backend.
+//!
+//! ```toml
+//! [domain]
+//! width = 200
+//! height = 100
+//! cell_size = 0.1
+//!
+//! [solver]
+//! method = "multigrid"
+//! cycle = "w"
+//!
+//! [boundaries]
+//! left = { inflow = { speed = 2.0, profile = "parabolic" } }
+//! right = "outflow"
+//!
+//! [[obstacles]]
+//! center = [0.2, 0.5]
+//! radius = 15.0
+//!
+//! [[scalars]]
+//! name = "dye"
+//! color = [1.0, 0.0, 0.0]
+//! emitters = [{ center = [0.02, 0.45], radius = 3.0, value = 1.0 }]
+//! initial = [{ center = [0.5, 0.5], radius = 10.0, value = 1.0 }]
+//!
+//! [[initial_velocity]]
+//! center = [0.5, 0.5]
+//! radius = 10.0
+//! velocity = [0.0, 1.0]
+//! ```
+//!
+//! Sections and keys that are left out take the defaults of [`SimConfig`], except that there is
+//! no obstacle unless one is listed.
+//!
+//! The solver `method` is one of `sor`, `jacobi`, `red-black`, `pcg` and `multigrid`, which takes
+//! a `cycle` of `v`, the default, or `w`.
+//!
+//! The fluid starts at rest and the scalars at 0, except within the `initial` regions of a scalar
+//! and the `[[initial_velocity]]` regions. These are given like the emitters: a `center` and a
+//! `radius`, a `wall`, or `mask = true` for the dye cells of the mask.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use image::ImageError;
+use macroquad::prelude::*;
+use serde::Deserialize;
+
+use crate::advect::{Backtrace, Scheme};
+use crate::boundary::Boundaries;
+use crate::config::{self, Buoyancy, ConfigError, Disk, InitialVelocity, Side, SimConfig};
+use crate::flip::Flip;
+use crate::grid::Grid;
+use crate::level_set::Liquid;
+use crate::mask::Mask;
+use crate::obstacle::Obstacle;
+use crate::pressure::{Cycle, ResidualNorm, Solver};
+use crate::sampler::{Boundary, Interpolation};
+use crate::scalar::{Emitter, Region, Scalar};
+use crate::tracer::{Integrator, Seed, Tracers};
+
+/// A validated simulation setup, usually read from a scene file with [`Scene::load`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scene {
+    /// Fluid cells along x.
+    pub width: usize,
+    /// Fluid cells along y.
+    pub height: usize,
+    /// Side of a cell in meters.
+    pub cell_size: f32,
+    /// Jacobi passes of the GPU pressure projection per step, which needs many more than the
+    /// CPU solvers.
+    pub gpu_iterations: usize,
+    pub config: SimConfig,
+}
+
+impl Default for Scene {
+    /// The channel of [`SimConfig::default`], with a disk obstacle in front of the inflow.
+    fn default() -> Scene {
+        Scene {
+            width: 200,
+            height: 100,
+            cell_size: 0.1,
+            gpu_iterations: 1000,
+            config: SimConfig::default(),
+        }
+    }
+}
+
+impl Scene {
+    /// Reads and validates the scene file at `path`. A mask image is looked up relative to the
+    /// directory of the file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Scene, SceneError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|error| SceneError::Io {
+            path: path.to_owned(),
+            error,
+        })?;
+        Scene::parse(&text, path.parent().unwrap_or(Path::new("")))
+    }
+
+    /// Parses and validates a scene from TOML `text`, looking up a mask image relative to `dir`.
+    pub fn parse(text: &str, dir: &Path) -> Result<Scene, SceneError> {
+        let file: File = toml::from_str(text).map_err(SceneError::Syntax)?;
+        let scene = file.into_scene(dir)?;
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        config::validate_grid(self.width, self.height, self.cell_size)?;
+        if self.gpu_iterations == 0 {
+            return Err(ConfigError::NoIterations);
+        }
+        self.config.validate()
+    }
+
+    /// A CPU grid set up for the scene.
+    pub fn grid(&self) -> Result<Grid, ConfigError> {
+        Grid::new(self.width, self.height, self.cell_size, self.config.clone())
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Syntax(toml::de::Error),
+    /// An entry of the file is ambiguous or incomplete, e.g. an obstacle with both a radius and
+    /// a half size.
+    Entry {
+        entry: String,
+        reason: &'static str,
+    },
+    Mask {
+        path: PathBuf,
+        error: ImageError,
+    },
+    Config(ConfigError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io { path, error } => {
+                write!(f, "cannot read scene {}: {error}", path.display())
+            }
+            SceneError::Syntax(error) => write!(f, "invalid scene file: {error}"),
+            SceneError::Entry { entry, reason } => write!(f, "{entry}: {reason}"),
+            SceneError::Mask { path, error } => {
+                write!(f, "cannot load mask {}: {error}", path.display())
+            }
+            SceneError::Config(error) => write!(f, "invalid scene: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io { error, .. } => Some(error),
+            SceneError::Syntax(error) => Some(error),
+            SceneError::Mask { error, .. } => Some(error),
+            SceneError::Config(error) => Some(error),
+            SceneError::Entry { .. } => None,
+        }
+    }
+}
+
+impl From<ConfigError> for SceneError {
+    fn from(error: ConfigError) -> SceneError {
+        SceneError::Config(error)
+    }
+}
+
+/// The layout of a scene file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    domain: Domain,
+    #[serde(default)]
+    solver: SolverSection,
+    #[serde(default)]
+    physics: Physics,
+    #[serde(default)]
+    boundaries: Boundaries,
+    #[serde(default)]
+    advection: Advection,
+    #[serde(default)]
+    obstacles: Vec<ObstacleEntry>,
+    #[serde(default)]
+    initial_velocity: Vec<InitialVelocityEntry>,
+    #[serde(default)]
+    liquid: Vec<LiquidEntry>,
+    buoyancy: Option<Buoyancy>,
+    #[serde(default)]
+    scalars: Vec<ScalarEntry>,
+    #[serde(default)]
+    tracers: TracersSection,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Domain {
+    width: usize,
+    height: usize,
+    cell_size: f32,
+    /// Image of the scene geometry, see [`SimConfig::mask`].
+    mask: Option<PathBuf>,
+    mask_speed: Option<f32>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SolverSection {
+    method: Option<Method>,
+    /// Cycle of the multigrid method.
+    cycle: Option<Cycle>,
+    iterations: Option<usize>,
+    tolerance: Option<f32>,
+    residual_norm: Option<ResidualNorm>,
+    over_relaxation: Option<f32>,
+    cfl: Option<f32>,
+    threads: Option<usize>,
+    gpu_iterations: Option<usize>,
+}
+
+/// The pressure solvers by name, with the multigrid cycle given as a key of its own.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Method {
+    Sor,
+    Jacobi,
+    RedBlack,
+    Pcg,
+    Multigrid,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Physics {
+    gravity: Option<f32>,
+    density: Option<f32>,
+    viscosity: Option<f32>,
+    vorticity_confinement: Option<f32>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Advection {
+    velocity: Option<Scheme>,
+    scalars: Option<Scheme>,
+    velocity_backtrace: Option<Backtrace>,
+    scalar_backtrace: Option<Backtrace>,
+    boundary: Option<Boundary>,
+    interpolation: Option<Interpolation>,
+    flip: Option<Flip>,
+}
+
+/// A disk with a `radius` or a rectangle with a `half_size`, in cells.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObstacleEntry {
+    center: [f32; 2],
+    radius: Option<f32>,
+    half_size: Option<[f32; 2]>,
+    #[serde(default)]
+    angle: f32,
+    #[serde(default)]
+    velocity: [f32; 2],
+    #[serde(default)]
+    angular_velocity: f32,
+}
+
+/// A rectangle from `min` to `max`, or a disk with a `center` and a `radius`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LiquidEntry {
+    min: Option<[f32; 2]>,
+    max: Option<[f32; 2]>,
+    center: Option<[f32; 2]>,
+    radius: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScalarEntry {
+    name: String,
+    /// Red, green and blue in `[0, 1]`.
+    color: [f32; 3],
+    #[serde(default)]
+    diffusion: f32,
+    #[serde(default)]
+    emitters: Vec<EmitterEntry>,
+    /// Values the scalar starts with, in the same regions as the emitters.
+    #[serde(default)]
+    initial: Vec<EmitterEntry>,
+}
+
+/// A velocity in the same regions as the emitters of the scalars.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InitialVelocityEntry {
+    center: Option<[f32; 2]>,
+    radius: Option<f32>,
+    wall: Option<Side>,
+    #[serde(default)]
+    mask: bool,
+    velocity: [f32; 2],
+}
+
+/// A disk with a `center` and a `radius`, a `wall`, or the dye cells of the `mask`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EmitterEntry {
+    center: Option<[f32; 2]>,
+    radius: Option<f32>,
+    wall: Option<Side>,
+    #[serde(default)]
+    mask: bool,
+    value: f32,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TracersSection {
+    integrator: Option<Integrator>,
+    trail: Option<usize>,
+    limit: Option<usize>,
+    #[serde(default)]
+    seeds: Vec<SeedEntry>,
+}
+
+/// A line from `start` to `end` with `count` tracers, or an emitter at `position`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SeedEntry {
+    start: Option<[f32; 2]>,
+    end: Option<[f32; 2]>,
+    count: Option<usize>,
+    position: Option<[f32; 2]>,
+}
+
+impl File {
+    fn into_scene(self, dir: &Path) -> Result<Scene, SceneError> {
+        let mut config = SimConfig {
+            obstacle: None,
+            ..SimConfig::default()
+        };
+        let mut gpu_iterations = Scene::default().gpu_iterations;
+
+        let solver = self.solver;
+        config.solver = match (solver.method, solver.cycle) {
+            (Some(Method::Multigrid), cycle) => Solver::Multigrid(cycle.unwrap_or_default()),
+            (_, Some(_)) => {
+                return Err(SceneError::Entry {
+                    entry: "solver.cycle".to_string(),
+                    reason: "only the multigrid method takes a cycle",
+                })
+            }
+            (Some(Method::Sor), None) => Solver::Sor,
+            (Some(Method::Jacobi), None) => Solver::Jacobi,
+            (Some(Method::RedBlack), None) => Solver::RedBlack,
+            (Some(Method::Pcg), None) => Solver::Pcg,
+            (None, None) => config.solver,
+        };
+        set(&mut config.iterations, solver.iterations);
+        config.tolerance = solver.tolerance;
+        set(&mut config.residual_norm, solver.residual_norm);
+        set(&mut config.over_relaxation, solver.over_relaxation);
+        set(&mut config.cfl, solver.cfl);
+        set(&mut config.threads, solver.threads);
+        set(&mut gpu_iterations, solver.gpu_iterations);
+
+        let physics = self.physics;
+        set(&mut config.gravity, physics.gravity);
+        set(&mut config.density, physics.density);
+        set(&mut config.viscosity, physics.viscosity);
+        set(
+            &mut config.vorticity_confinement,
+            physics.vorticity_confinement,
+        );
+        config.boundaries = self.boundaries;
+
+        let advection = self.advection;
+        set(&mut config.velocity_advection, advection.velocity);
+        set(&mut config.scalar_advection, advection.scalars);
+        set(&mut config.velocity_backtrace, advection.velocity_backtrace);
+        set(&mut config.scalar_backtrace, advection.scalar_backtrace);
+        set(&mut config.boundary, advection.boundary);
+        set(&mut config.interpolation, advection.interpolation);
+        config.flip = advection.flip;
+
+        set(&mut config.mask_speed, self.domain.mask_speed);
+        if let Some(path) = self.domain.mask {
+            let path = dir.join(path);
+            let mask = Mask::load(&path).map_err(|error| SceneError::Mask { path, error })?;
+            config.mask = Some(mask);
+        }
+
+        let entry = |list: &str, index: usize, reason| SceneError::Entry {
+            entry: format!("{list}[{index}]"),
+            reason,
+        };
+        for (index, obstacle) in self.obstacles.iter().enumerate() {
+            let obstacle = obstacle
+                .obstacle()
+                .map_err(|r| entry("obstacles", index, r))?;
+            config.obstacles.push(obstacle);
+        }
+        for (index, initial) in self.initial_velocity.iter().enumerate() {
+            let initial = initial
+                .initial_velocity()
+                .map_err(|r| entry("initial_velocity", index, r))?;
+            config.initial_velocity.push(initial);
+        }
+        for (index, liquid) in self.liquid.iter().enumerate() {
+            let liquid = liquid.liquid().map_err(|r| entry("liquid", index, r))?;
+            config.liquid.push(liquid);
+        }
+        config.buoyancy = self.buoyancy;
+        for (index, scalar) in self.scalars.iter().enumerate() {
+            let [r, g, b] = scalar.color;
+            let emitters = |entries: &[EmitterEntry]| {
+                let emitters = entries.iter().map(EmitterEntry::emitter);
+                emitters
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|r| entry("scalars", index, r))
+            };
+            config.scalars.push(Scalar {
+                diffusion: scalar.diffusion,
+                emitters: emitters(&scalar.emitters)?,
+                initial: emitters(&scalar.initial)?,
+                ..Scalar::new(scalar.name.clone(), Color::new(r, g, b, 1.0))
+            });
+        }
+
+        let tracers = self.tracers;
+        let mut seeds = Vec::new();
+        for (index, seed) in tracers.seeds.iter().enumerate() {
+            seeds.push(seed.seed().map_err(|r| entry("tracers.seeds", index, r))?);
+        }
+        let defaults = Tracers::default();
+        config.tracers = Tracers {
+            seeds,
+            integrator: tracers.integrator.unwrap_or(defaults.integrator),
+            trail: tracers.trail.unwrap_or(defaults.trail),
+            limit: tracers.limit.unwrap_or(defaults.limit),
+        };
+
+        Ok(Scene {
+            width: self.domain.width,
+            height: self.domain.height,
+            cell_size: self.domain.cell_size,
+            gpu_iterations,
+            config,
+        })
+    }
+}
+
+impl ObstacleEntry {
+    fn obstacle(&self) -> Result<Obstacle, &'static str> {
+        let center = Vec2::from(self.center);
+        let obstacle = match (self.radius, self.half_size) {
+            (Some(radius), None) => Obstacle::disk(center, radius),
+            (None, Some(half_size)) => Obstacle::rect(center, half_size.into()),
+            _ => return Err("an obstacle needs either a radius or a half_size"),
+        };
+        Ok(Obstacle {
+            angle: self.angle,
+            ..obstacle
+        }
+        .with_velocity(self.velocity.into())
+        .with_angular_velocity(self.angular_velocity))
+    }
+}
+
+impl LiquidEntry {
+    fn liquid(&self) -> Result<Liquid, &'static str> {
+        match (self.min, self.max, self.center, self.radius) {
+            (Some(min), Some(max), None, None) => Ok(Liquid::Rect {
+                min: min.into(),
+                max: max.into(),
+            }),
+            (None, None, Some(center), Some(radius)) => Ok(Liquid::Disk(Disk {
+                center: center.into(),
+                radius,
+            })),
+            _ => Err("liquid needs either a min and a max, or a center and a radius"),
+        }
+    }
+}
+
+/// A disk with a `center` and a `radius`, a `wall`, or the dye cells of the `mask`, unless the
+/// keys given are ambiguous or incomplete.
+fn region(
+    center: Option<[f32; 2]>,
+    radius: Option<f32>,
+    wall: Option<Side>,
+    mask: bool,
+) -> Option<Region> {
+    match (center, radius, wall, mask) {
+        (Some(center), Some(radius), None, false) => Some(Region::Disk(Disk {
+            center: center.into(),
+            radius,
+        })),
+        (None, None, Some(side), false) => Some(Region::Wall(side)),
+        (None, None, None, true) => Some(Region::Mask),
+        _ => None,
+    }
+}
+
+impl EmitterEntry {
+    fn emitter(&self) -> Result<Emitter, &'static str> {
+        let region = region(self.center, self.radius, self.wall, self.mask)
+            .ok_or("a region needs either a center and a radius, a wall, or mask = true")?;
+        Ok(Emitter {
+            region,
+            value: self.value,
+        })
+    }
+}
+
+impl InitialVelocityEntry {
+    fn initial_velocity(&self) -> Result<InitialVelocity, &'static str> {
+        let region = region(self.center, self.radius, self.wall, self.mask).ok_or(
+            "an initial velocity needs either a center and a radius, a wall, or mask = true",
+        )?;
+        Ok(InitialVelocity {
+            region,
+            velocity: self.velocity.into(),
+        })
+    }
+}
+
+impl SeedEntry {
+    fn seed(&self) -> Result<Seed, &'static str> {
+        match (self.start, self.end, self.count, self.position) {
+            (Some(start), Some(end), Some(count), None) => Ok(Seed::Line {
+                start: start.into(),
+                end: end.into(),
+                count,
+            }),
+            (None, None, None, Some(position)) => Ok(Seed::Emitter {
+                position: position.into(),
+            }),
+            _ => Err("a seed needs either a start, an end and a count, or a position"),
+        }
+    }
+}
+
+/// Overwrites `field` with `value` if the file sets it.
+fn set<T>(field: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *field = value;
+    }
+}
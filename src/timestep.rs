@@ -0,0 +1,149 @@
+//! Time steps limited by the CFL condition.
+
+use crate::pressure::SolveReport;
+
+/// Most substeps per frame. A simulation that needs more has blown up, and with ever shorter
+/// substeps the frame would never end.
+pub const MAX_SUBSTEPS: usize = 100;
+
+/// Time step that moves a fluid parcel at `max_speed` by at most `cfl` cells of side `size`,
+/// infinity in a fluid at rest and NaN for a NaN speed.
+pub fn cfl_dt(max_speed: f32, size: f32, cfl: f32) -> f32 {
+    if max_speed == 0.0 {
+        f32::INFINITY
+    } else {
+        cfl * size / max_speed
+    }
+}
+
+/// Next substep of at most `max_dt` with `remaining` seconds left in the frame. Instead of ending
+/// the frame with a sliver of a step, the last two substeps share the remaining time evenly.
+pub fn substep(max_dt: f32, remaining: f32) -> f32 {
+    if max_dt >= remaining {
+        remaining
+    } else if 2.0 * max_dt > remaining {
+        remaining / 2.0
+    } else {
+        max_dt
+    }
+}
+
+/// Advances `state` by `frame_time` seconds in substeps of at most `max_dt(state)`, calling
+/// `step(state, dt)` for each of them.
+///
+/// Stops early and reports the frame as [`Substeps::truncated`] if `max_dt` is NaN or not
+/// positive, or after [`MAX_SUBSTEPS`], e.g. once the velocity has blown up.
+pub fn advance<T: ?Sized>(
+    state: &mut T,
+    frame_time: f32,
+    max_dt: impl Fn(&T) -> f32,
+    mut step: impl FnMut(&mut T, f32),
+) -> Substeps {
+    let mut substeps = Substeps::default();
+    let mut remaining = frame_time;
+    while remaining > 0.0 {
+        let dt = substep(max_dt(state), remaining);
+        if dt.is_nan() || dt <= 0.0 || substeps.count == MAX_SUBSTEPS {
+            substeps.truncated = true;
+            break;
+        }
+        step(state, dt);
+        substeps.push(dt);
+        remaining = if dt == remaining { 0.0 } else { remaining - dt };
+    }
+    substeps
+}
+
+/// The time steps taken to advance by one frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Substeps {
+    pub count: usize,
+    /// Total time advanced in seconds.
+    pub time: f32,
+    /// Shortest substep in seconds, 0 without substeps.
+    pub min_dt: f32,
+    /// Longest substep in seconds.
+    pub max_dt: f32,
+    /// Whether the frame was cut short of its time, see [`advance`].
+    pub truncated: bool,
+}
+
+impl Substeps {
+    fn push(&mut self, dt: f32) {
+        self.min_dt = if self.count == 0 {
+            dt
+        } else {
+            self.min_dt.min(dt)
+        };
+        self.max_dt = self.max_dt.max(dt);
+        self.time += dt;
+        self.count += 1;
+    }
+}
+
+/// Outcome of advancing a [`Grid`](crate::Grid) by one frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StepReport {
+    pub substeps: Substeps,
+    /// Pressure solve of the last substep.
+    pub solve: SolveReport,
+}
+
+/// How a [`FixedStep`] clock relates simulated time to wall clock time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Pacing {
+    /// Simulated time follows the wall clock.
+    #[default]
+    RealTime,
+    /// Every frame runs the maximum number of steps, as fast as the simulation allows.
+    FastForward,
+}
+
+/// Decides how many fixed steps to run per rendered frame, so simulated time does not depend on
+/// the frame rate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FixedStep {
+    /// Simulated seconds per step.
+    pub step: f32,
+    /// Most steps per frame. A simulation that cannot keep up with the wall clock drops the time
+    /// it is behind instead of running ever more steps per frame.
+    pub max_steps: usize,
+    pub pacing: Pacing,
+    accumulator: f32,
+}
+
+impl FixedStep {
+    pub fn new(step: f32, max_steps: usize) -> FixedStep {
+        FixedStep {
+            step,
+            max_steps,
+            pacing: Pacing::RealTime,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Switches between real time and fast forward.
+    pub fn toggle_pacing(&mut self) {
+        self.pacing = match self.pacing {
+            Pacing::RealTime => Pacing::FastForward,
+            Pacing::FastForward => Pacing::RealTime,
+        };
+        self.accumulator = 0.0;
+    }
+
+    /// Number of steps to run for a frame that took `frame_time` wall clock seconds.
+    pub fn steps(&mut self, frame_time: f32) -> usize {
+        if self.pacing == Pacing::FastForward {
+            return self.max_steps;
+        }
+
+        self.accumulator += frame_time;
+        let steps = (self.accumulator / self.step) as usize;
+        if steps > self.max_steps {
+            self.accumulator %= self.step;
+            return self.max_steps;
+        }
+        self.accumulator -= steps as f32 * self.step;
+        steps
+    }
+}
@@ -0,0 +1,134 @@
+//! Massless marker particles that make the Lagrangian transport of the flow visible.
+
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+use serde::Deserialize;
+
+/// Integration of the tracer paths through the velocity field.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Integrator {
+    /// Midpoint rule, one extra velocity sample per step.
+    #[default]
+    Rk2,
+    /// Classic fourth order Runge-Kutta, three extra velocity samples per step.
+    Rk4,
+}
+
+/// Where tracers are released, with positions given as fractions of the domain like the center
+/// of a [`Disk`](crate::Disk).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Seed {
+    /// `count` tracers evenly spaced from `start` to `end`, released once at the start.
+    Line {
+        start: Vec2,
+        end: Vec2,
+        count: usize,
+    },
+    /// One tracer per step at `position`. Together they trace out the streakline through it.
+    Emitter { position: Vec2 },
+}
+
+/// Tracer particles of a [`Grid`](crate::Grid), see [`SimConfig::tracers`](crate::SimConfig::tracers).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tracers {
+    pub seeds: Vec<Seed>,
+    pub integrator: Integrator,
+    /// Past positions kept per tracer and drawn as a fading trail, 0 to draw points.
+    pub trail: usize,
+    /// Most tracers alive at once, the oldest ones are removed first.
+    pub limit: usize,
+}
+
+impl Default for Tracers {
+    fn default() -> Tracers {
+        Tracers {
+            seeds: Vec::new(),
+            integrator: Integrator::Rk2,
+            trail: 0,
+            limit: 10_000,
+        }
+    }
+}
+
+impl Tracers {
+    /// Releases the tracers of the seeds into `tracers` on a grid of `width` x `height` cells.
+    /// Lines only release theirs at the `start`.
+    pub fn release(&self, width: usize, height: usize, start: bool, tracers: &mut Vec<Tracer>) {
+        let scale = vec2(width as f32, height as f32);
+        for seed in &self.seeds {
+            match *seed {
+                Seed::Line {
+                    start: a,
+                    end: b,
+                    count,
+                } if start => {
+                    for n in 0..count {
+                        let t = if count > 1 {
+                            n as f32 / (count - 1) as f32
+                        } else {
+                            0.5
+                        };
+                        tracers.push(Tracer::new(a.lerp(b, t) * scale));
+                    }
+                }
+                Seed::Line { .. } => {}
+                Seed::Emitter { position } => tracers.push(Tracer::new(position * scale)),
+            }
+        }
+    }
+}
+
+/// One tracer particle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tracer {
+    /// Position in cell units, where the center of cell `(i, j)` is at `(i, j)`.
+    pub position: Vec2,
+    /// Past positions, the most recent last.
+    pub trail: VecDeque<Vec2>,
+}
+
+impl Tracer {
+    pub fn new(position: Vec2) -> Tracer {
+        Tracer {
+            position,
+            trail: VecDeque::new(),
+        }
+    }
+
+    /// Moves the tracer `step = dt / size` cells along `velocity(position)`, remembering up to
+    /// `trail` past positions.
+    pub fn advance(
+        &mut self,
+        velocity: impl Fn(Vec2) -> Vec2,
+        step: f32,
+        integrator: Integrator,
+        trail: usize,
+    ) {
+        if trail > 0 {
+            if self.trail.len() == trail {
+                self.trail.pop_front();
+            }
+            self.trail.push_back(self.position);
+        }
+
+        let x = self.position;
+        self.position += match integrator {
+            Integrator::Rk2 => velocity(x + velocity(x) * step / 2.0) * step,
+            Integrator::Rk4 => {
+                let k1 = velocity(x);
+                let k2 = velocity(x + k1 * step / 2.0);
+                let k3 = velocity(x + k2 * step / 2.0);
+                let k4 = velocity(x + k3 * step);
+                (k1 + 2.0 * k2 + 2.0 * k3 + k4) * step / 6.0
+            }
+        };
+    }
+
+    /// Cell containing the tracer, `None` outside of the grid.
+    pub fn cell(&self) -> Option<(usize, usize)> {
+        let x = self.position + vec2(0.5, 0.5);
+        (x.x >= 0.0 && x.y >= 0.0).then_some((x.x as usize, x.y as usize))
+    }
+}
@@ -0,0 +1,124 @@
+use std::f32::consts::PI;
+
+use euler::advect::{self, Path};
+use euler::{Backtrace, Boundary, Field2, Interpolation, Scheme, Stagger};
+use macroquad::prelude::*;
+
+/// Interior cells along each side of the test grids.
+const N: usize = 64;
+
+fn fluid() -> Field2 {
+    Field2::new(N + 2, N + 2, Stagger::Center, 1.0)
+}
+
+/// Velocity at the center of cell `(i, j)`, `None` outside the interior.
+fn center_velocity<'a>(
+    u: &'a Field2,
+    v: &'a Field2,
+) -> impl Fn(usize, usize) -> Option<Vec2> + Sync + 'a {
+    move |i, j| {
+        let inside = (1..=N).contains(&i) && (1..=N).contains(&j);
+        inside.then(|| {
+            vec2(
+                (u[(i, j)] + u[(i + 1, j)]) / 2.0,
+                (v[(i, j)] + v[(i, j + 1)]) / 2.0,
+            )
+        })
+    }
+}
+
+/// Advects `field` with `scheme` and `backtrace` through `u` and `v` for `steps` steps of one
+/// cell unit of time.
+fn run(
+    field: &mut Field2,
+    u: &Field2,
+    v: &Field2,
+    scheme: Scheme,
+    backtrace: Backtrace,
+    steps: usize,
+) {
+    let s = fluid();
+    let path = Path {
+        backtrace,
+        u,
+        v,
+        s: &s,
+        boundary: Boundary::Clamp,
+        interpolation: Interpolation::Linear,
+    };
+    let velocity = center_velocity(u, v);
+    for _ in 0..steps {
+        advect::advect(field, scheme, &path, &velocity, 1.0);
+    }
+}
+
+#[test]
+fn limited_schemes_create_no_new_extrema_at_a_step() {
+    let u = Field2::new(N + 2, N + 2, Stagger::XFace, 0.3);
+    let v = Field2::new(N + 2, N + 2, Stagger::YFace, 0.2);
+    let mut step = Field2::new(N + 2, N + 2, Stagger::Center, 0.0);
+    for (i, j) in step.interior() {
+        if (10..30).contains(&i) && (10..30).contains(&j) {
+            step[(i, j)] = 1.0;
+        }
+    }
+    for scheme in [Scheme::MacCormack, Scheme::Bfecc] {
+        let mut field = step.clone();
+        run(&mut field, &u, &v, scheme, Backtrace::Euler, 60);
+        let (min, max) = field
+            .data()
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(a, b), &x| {
+                (a.min(x), b.max(x))
+            });
+        assert!(min >= 0.0 && max <= 1.0, "{scheme:?}: {min} to {max}");
+    }
+}
+
+/// Largest error in a disk around the center after advecting the linear field `x` through one
+/// turn of a rigid rotation in `steps` steps. Linear interpolation reproduces the rotated field
+/// exactly, so the error comes from the backtrace alone.
+fn rotation_error(backtrace: Backtrace, steps: usize) -> f32 {
+    let center = (N as f32 + 1.0) / 2.0;
+    let omega = 2.0 * PI / steps as f32;
+    let mut u = Field2::new(N + 2, N + 2, Stagger::XFace, 0.0);
+    let mut v = Field2::new(N + 2, N + 2, Stagger::YFace, 0.0);
+    let mut x = Field2::new(N + 2, N + 2, Stagger::Center, 0.0);
+    for j in 0..N + 2 {
+        for i in 0..N + 2 {
+            let (fi, fj) = (i as f32 - center, j as f32 - center);
+            u[(i, j)] = -omega * fj;
+            v[(i, j)] = omega * fi;
+            x[(i, j)] = fi;
+        }
+    }
+    let exact = x.clone();
+    run(&mut x, &u, &v, Scheme::SemiLagrangian, backtrace, steps);
+    x.interior()
+        .filter(|&(i, j)| vec2(i as f32 - center, j as f32 - center).length() < N as f32 / 4.0)
+        .map(|c| (x[c] - exact[c]).abs())
+        .fold(0.0, f32::max)
+}
+
+#[test]
+fn backtraces_converge_at_their_order_under_rotation() {
+    let orders = [
+        (Backtrace::Euler, 1.0),
+        (Backtrace::Rk2, 2.0),
+        (Backtrace::Rk3, 3.0),
+    ];
+    let mut previous = f32::INFINITY;
+    for (backtrace, order) in orders {
+        let (coarse, fine) = (
+            rotation_error(backtrace, 50),
+            rotation_error(backtrace, 100),
+        );
+        assert!(
+            coarse < previous,
+            "{backtrace:?}: {coarse} after {previous}"
+        );
+        let observed = (coarse / fine).log2();
+        assert!(observed > order - 0.5, "{backtrace:?}: order {observed}");
+        previous = coarse;
+    }
+}
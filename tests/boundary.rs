@@ -0,0 +1,142 @@
+use euler::{Boundaries, BoundaryCondition, Grid, Inflow, Mask, Profile, SimConfig, Variation};
+use image::{Rgba, RgbaImage};
+
+const WIDTH: usize = 32;
+const HEIGHT: usize = 16;
+
+/// A channel from left to right with `inflow` on the left.
+fn channel(inflow: Inflow) -> SimConfig {
+    with_outlet(inflow, BoundaryCondition::Outflow)
+}
+
+/// An unobstructed channel without gravity from `inflow` on the left to `outlet` on the right.
+fn with_outlet(inflow: Inflow, outlet: BoundaryCondition) -> SimConfig {
+    SimConfig {
+        boundaries: Boundaries {
+            left: BoundaryCondition::Inflow(inflow),
+            right: outlet,
+            ..Boundaries::walls()
+        },
+        gravity: 0.0,
+        obstacle: None,
+        ..SimConfig::default()
+    }
+}
+
+/// Flux across the left and the right side in m²/s.
+fn fluxes(grid: &Grid) -> (f32, f32) {
+    let flux = |i| (1..=HEIGHT).map(|j| grid.u()[(i, j)]).sum::<f32>() * grid.size();
+    (flux(1), flux(WIDTH + 1))
+}
+
+#[test]
+fn inflows_never_draw_fluid_out() {
+    let error = channel(Inflow::uniform(-1.0)).validate().unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("inflow speed must not be negative"),
+        "{error}"
+    );
+
+    let pulse = Variation::Pulse {
+        amplitude: 1.5,
+        period: 1.0,
+    };
+    let error = channel(Inflow::uniform(1.0).with_variation(pulse)).validate();
+    assert!(error.unwrap_err().to_string().contains("pulse amplitude"));
+
+    assert!(channel(Inflow::uniform(0.0)).validate().is_ok());
+}
+
+#[test]
+fn outlets_carry_away_what_the_inflow_brings_in() {
+    let inflow = Inflow::uniform(1.0).with_profile(Profile::Parabolic);
+    let outlets = [
+        BoundaryCondition::Outflow,
+        BoundaryCondition::Convective { speed: None },
+        BoundaryCondition::Convective { speed: Some(0.5) },
+    ];
+    for outlet in outlets {
+        let mut grid = Grid::new(WIDTH, HEIGHT, 0.1, with_outlet(inflow, outlet)).unwrap();
+        for _ in 0..50 {
+            grid.step(0.02);
+            let (inflow, outflow) = fluxes(&grid);
+            assert!(inflow > 0.0);
+            assert!(
+                (outflow - inflow).abs() < 1e-4 * inflow,
+                "{outlet:?} at {} s: {outflow} out for {inflow} in",
+                grid.time()
+            );
+        }
+    }
+}
+
+#[test]
+fn parabolic_inflow_peaks_at_mid_span() {
+    let inflow = Inflow::uniform(2.0).with_profile(Profile::Parabolic);
+    let grid = Grid::new(WIDTH, HEIGHT, 0.1, channel(inflow)).unwrap();
+    let u = |j| grid.u()[(1, j)];
+    // Rising from the bottom to the two middle rows, and falling symmetrically above them.
+    assert!(u(1) > 0.0);
+    for j in 1..HEIGHT / 2 {
+        assert!(u(j) < u(j + 1), "row {j}");
+    }
+    for j in 1..=HEIGHT {
+        assert!(
+            (u(j) - u(HEIGHT + 1 - j)).abs() < 1e-5,
+            "asymmetric at row {j}"
+        );
+    }
+    // The middle rows are within half a cell of the peak.
+    let peak = u(HEIGHT / 2);
+    assert!(
+        peak > 2.0 * (1.0 - 1.0 / HEIGHT as f32) && peak <= 2.0,
+        "peak {peak}"
+    );
+}
+
+/// A channel without gravity from a red column of inflow cells on the left to `outlet` on the
+/// right, with `width` x `height` cells.
+fn mask_channel(width: usize, height: usize, outlet: BoundaryCondition) -> SimConfig {
+    let mut image = RgbaImage::from_pixel(width as u32, height as u32, Rgba([255; 4]));
+    for y in 0..height as u32 {
+        image.put_pixel(0, y, Rgba([255, 0, 0, 255]));
+    }
+    SimConfig {
+        mask: Some(Mask::from_image(&image)),
+        boundaries: Boundaries {
+            right: outlet,
+            ..Boundaries::walls()
+        },
+        gravity: 0.0,
+        obstacle: None,
+        ..SimConfig::default()
+    }
+}
+
+#[test]
+fn outlets_carry_away_what_mask_inflow_cells_bring_in() {
+    let error = mask_channel(WIDTH, HEIGHT, BoundaryCondition::Wall)
+        .validate()
+        .unwrap_err();
+    assert!(error.to_string().contains("mask inflow"), "{error}");
+
+    let (width, height) = (40, 20);
+    let outlets = [
+        BoundaryCondition::Outflow,
+        BoundaryCondition::Convective { speed: None },
+    ];
+    for outlet in outlets {
+        let config = mask_channel(width, height, outlet);
+        let speed = config.mask_speed;
+        let mut grid = Grid::new(width, height, 0.1, config).unwrap();
+        for _ in 0..20 {
+            grid.step(0.02);
+        }
+        for j in 1..=height {
+            let u = grid.u()[(width / 2, j)];
+            assert!((u - speed).abs() < 1e-3, "{outlet:?} row {j}: {u}");
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use euler::{Boundaries, Buoyancy, Grid, Scalar, Side, SimConfig};
+use macroquad::prelude::RED;
+
+/// A closed box heated from below and cooled from above, water-like with 1 cm cells.
+fn rayleigh_benard() -> Grid {
+    let temperature = Scalar::new("temperature", RED)
+        .with_diffusion(1e-4)
+        .with_emitter(Side::Bottom, 1.0)
+        .with_emitter(Side::Top, -1.0);
+    let config = SimConfig {
+        obstacle: None,
+        boundaries: Boundaries::walls(),
+        scalars: vec![temperature],
+        buoyancy: Some(Buoyancy::default()),
+        ..SimConfig::default()
+    };
+    Grid::new(64, 32, 0.01, config).unwrap()
+}
+
+#[test]
+fn heated_floor_under_cooled_ceiling_forms_convection_cells() {
+    let mut grid = rayleigh_benard();
+    for _ in 0..100 {
+        grid.advance(0.02);
+    }
+    let speed = grid.max_velocity();
+    assert!(
+        speed > 0.05,
+        "no convection after 2 s, max speed {speed} m/s"
+    );
+}
@@ -0,0 +1,71 @@
+use euler::{
+    Boundaries, Disk, Field2, Flip, Grid, InitialVelocity, Particles, Region, SimConfig, Stagger,
+};
+use macroquad::prelude::*;
+
+const WIDTH: usize = 48;
+const HEIGHT: usize = 48;
+
+/// A closed box without gravity where a jet starts off to the right and rolls up into a pair of
+/// vortices, carried by particles with the FLIP `ratio`.
+fn jet(ratio: f32) -> Grid {
+    let jet = InitialVelocity {
+        region: Region::Disk(Disk {
+            center: vec2(0.3, 0.5),
+            radius: 6.0,
+        }),
+        velocity: vec2(1.0, 0.0),
+    };
+    let config = SimConfig {
+        gravity: 0.0,
+        obstacle: None,
+        boundaries: Boundaries::walls(),
+        initial_velocity: vec![jet],
+        flip: Some(Flip {
+            ratio,
+            ..Flip::default()
+        }),
+        ..SimConfig::default()
+    };
+    Grid::new(WIDTH, HEIGHT, 0.01, config).unwrap()
+}
+
+/// Kinetic energy per unit density and depth, in m⁴/s².
+fn kinetic_energy(grid: &Grid) -> f32 {
+    let h2 = grid.size() * grid.size();
+    let sum = |field: &Field2| field.data().iter().map(|u| u * u).sum::<f32>();
+    (sum(grid.u()) + sum(grid.v())) * h2 / 2.0
+}
+
+#[test]
+fn pic_loses_more_energy_than_flip() {
+    let energy = |ratio| {
+        let mut grid = jet(ratio);
+        let initial = kinetic_energy(&grid);
+        for _ in 0..100 {
+            grid.step(0.005);
+        }
+        kinetic_energy(&grid) / initial
+    };
+    let (pic, flip) = (energy(0.0), energy(1.0));
+    assert!(
+        flip > 2.0 * pic,
+        "PIC keeps {pic}, FLIP {flip} of the energy"
+    );
+}
+
+#[test]
+fn reseeding_bounds_the_particle_count() {
+    let (nx, ny) = (WIDTH + 2, HEIGHT + 2);
+    let u = Field2::new(nx, ny, Stagger::XFace, 0.0);
+    let v = Field2::new(nx, ny, Stagger::YFace, 0.0);
+    let mut particles = Particles::new(&u, &v);
+    particles.reseed(2, |_, _| true, |_| Vec2::ZERO);
+    let cells = WIDTH * HEIGHT;
+    assert_eq!(particles.len(), 4 * cells);
+
+    // Everything converges into one cell, and the cells it left are refilled
+    particles.positions.fill(vec2(10.0, 10.0));
+    particles.reseed(2, |_, _| true, |_| Vec2::ZERO);
+    assert_eq!(particles.len(), 8 + 4 * (cells - 1));
+}
@@ -0,0 +1,109 @@
+use std::fs;
+use std::path::PathBuf;
+
+use euler::{Boundaries, Cycle, Grid, Mask, SimConfig, Solver};
+use image::{Rgba, RgbaImage};
+
+const WIDTH: usize = 8;
+const HEIGHT: usize = 6;
+
+/// Path of a file in the temporary directory, named after the test using it.
+fn temporary(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("euler-mask-{}-{name}", std::process::id()))
+}
+
+/// A closed tank whose interior is painted by `mask` pixel by pixel, without gravity.
+fn grid(mask: Mask, solver: Solver) -> Grid {
+    let config = SimConfig {
+        mask: Some(mask),
+        solver,
+        tolerance: Some(1e-5),
+        boundaries: Boundaries::walls(),
+        obstacle: None,
+        gravity: 0.0,
+        ..SimConfig::default()
+    };
+    Grid::new(WIDTH, HEIGHT, 0.1, config).unwrap()
+}
+
+fn solid_cells(grid: &Grid) -> usize {
+    grid.s()
+        .interior_values()
+        .filter(|&(_, s)| s == 0.0)
+        .count()
+}
+
+#[test]
+fn dark_pixels_of_a_pgm_are_solid() {
+    // A black 2x2 block and a dark gray pixel in a white image.
+    let mut text = format!("P2\n{WIDTH} {HEIGHT}\n255\n");
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let gray = match (x, y) {
+                (3..=4, 2..=3) => 0,
+                (0, 0) => 60,
+                (7, 5) => 200,
+                _ => 255,
+            };
+            text += &format!("{gray} ");
+        }
+        text += "\n";
+    }
+    let path = temporary("block.pgm");
+    fs::write(&path, text).unwrap();
+    let mask = Mask::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(mask.size(), (WIDTH, HEIGHT));
+    let grid = grid(mask, Solver::default());
+    assert_eq!(solid_cells(&grid), 5);
+    // The top row of the image is the top row of cells.
+    assert_eq!(grid.s()[(1, HEIGHT)], 0.0);
+    assert_eq!(grid.s()[(WIDTH, 1)], 1.0);
+}
+
+#[test]
+fn inflow_blows_into_its_fluid_neighbour_every_step() {
+    // Inflow cells along the right side blow to the left, through a black block, into outflow
+    // cells along the left side.
+    let mut image = RgbaImage::from_pixel(WIDTH as u32, HEIGHT as u32, Rgba([255; 4]));
+    for y in 0..HEIGHT as u32 {
+        image.put_pixel(0, y, Rgba([0, 0, 255, 255]));
+        image.put_pixel(WIDTH as u32 - 1, y, Rgba([255, 0, 0, 255]));
+    }
+    for (x, y) in [(3, 2), (4, 2), (3, 3), (4, 3)] {
+        image.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+    }
+    let path = temporary("channel.png");
+    image.save(&path).unwrap();
+    let mask = Mask::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let solvers = [
+        Solver::Sor,
+        Solver::Jacobi,
+        Solver::RedBlack,
+        Solver::Pcg,
+        Solver::Multigrid(Cycle::V),
+    ];
+    for solver in solvers {
+        let mut grid = grid(mask.clone(), solver);
+        let speed = grid.config().mask_speed;
+        assert_eq!(solid_cells(&grid), 2 * HEIGHT + 4);
+        for _ in 0..10 {
+            grid.step(0.01);
+            for j in 1..=HEIGHT {
+                assert_eq!(grid.u()[(WIDTH, j)], -speed, "{solver:?} inflow of row {j}");
+                assert_eq!(grid.u()[(2, j)], -speed, "{solver:?} outflow of row {j}");
+            }
+        }
+        // The fluid in between carries the same flux around the block, up to what advecting the
+        // projected velocity changes.
+        let flux: f32 = (1..=HEIGHT).map(|j| grid.u()[(4, j)]).sum();
+        let error = (flux + HEIGHT as f32 * speed).abs();
+        assert!(
+            error < 0.05 * HEIGHT as f32 * speed,
+            "{solver:?} flux {flux}"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use euler::{Boundaries, Grid, Obstacle, SimConfig};
+use macroquad::prelude::*;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+const SIZE: f32 = 0.01;
+
+/// A closed box without gravity or the default disk, stirred by `obstacle`.
+fn stirred(obstacle: Obstacle) -> Grid {
+    let config = SimConfig {
+        gravity: 0.0,
+        obstacle: None,
+        boundaries: Boundaries::walls(),
+        obstacles: vec![obstacle],
+        ..SimConfig::default()
+    };
+    Grid::new(WIDTH, HEIGHT, SIZE, config).unwrap()
+}
+
+/// Asserts that every face of a cell of the moving obstacle carries its velocity.
+fn assert_faces_move_with_the_obstacle(grid: &Grid) {
+    let obstacle = grid.obstacles()[0];
+    let inside = |i: usize, j: usize| obstacle.contains(vec2(i as f32, j as f32), WIDTH, HEIGHT);
+    let velocity = |x: Vec2| obstacle.velocity_at(x, WIDTH, HEIGHT, SIZE);
+    let mut faces = 0;
+    for j in 1..=HEIGHT {
+        for i in 1..=WIDTH {
+            if !inside(i, j) {
+                continue;
+            }
+            let sides = [
+                (grid.u()[(i, j)], velocity(vec2(i as f32 - 0.5, j as f32)).x),
+                (
+                    grid.u()[(i + 1, j)],
+                    velocity(vec2(i as f32 + 0.5, j as f32)).x,
+                ),
+                (grid.v()[(i, j)], velocity(vec2(i as f32, j as f32 - 0.5)).y),
+                (
+                    grid.v()[(i, j + 1)],
+                    velocity(vec2(i as f32, j as f32 + 0.5)).y,
+                ),
+            ];
+            for (value, expected) in sides {
+                assert!(
+                    (value - expected).abs() < 1e-5,
+                    "{value} instead of {expected} at ({i}, {j})"
+                );
+                faces += 1;
+            }
+        }
+    }
+    assert!(faces > 0);
+}
+
+#[test]
+fn faces_next_to_a_towed_obstacle_carry_its_velocity() {
+    let towed = Obstacle::disk(vec2(0.3, 0.5), 5.0).with_velocity(vec2(0.2, 0.0));
+    let mut grid = stirred(towed);
+    for _ in 0..5 {
+        grid.step(0.01);
+        assert_faces_move_with_the_obstacle(&grid);
+    }
+}
+
+#[test]
+fn faces_next_to_a_rotating_obstacle_carry_its_velocity() {
+    let paddle = Obstacle::rect(vec2(0.5, 0.5), vec2(10.0, 2.0)).with_angular_velocity(2.0);
+    let mut grid = stirred(paddle);
+    for _ in 0..5 {
+        grid.step(0.01);
+        assert_faces_move_with_the_obstacle(&grid);
+    }
+}
+
+#[test]
+fn cells_a_towed_obstacle_leaves_become_fluid_again() {
+    let towed = Obstacle::disk(vec2(0.3, 0.5), 5.0).with_velocity(vec2(0.2, 0.0));
+    let mut grid = stirred(towed);
+    let solid = |grid: &Grid| -> Vec<(usize, usize)> {
+        grid.s()
+            .interior()
+            .filter(|&c| grid.s()[c] == 0.0)
+            .collect()
+    };
+    let before = solid(&grid);
+    for _ in 0..50 {
+        grid.step(0.01);
+    }
+
+    // 0.1 m in 0.5 s, or 10 cells to the right
+    let center = grid.obstacles()[0].center_in_cells(WIDTH, HEIGHT);
+    assert!((center - vec2(0.3 * WIDTH as f32 + 10.0, HEIGHT as f32 / 2.0)).length() < 1e-3);
+    assert_eq!(solid(&grid).len(), before.len());
+    assert!(
+        before.iter().all(|&c| grid.s()[c] != 0.0),
+        "the obstacle did not leave its cells"
+    );
+}
@@ -0,0 +1,203 @@
+use euler::{
+    Boundaries, BoundaryCondition, Cycle, Field2, Grid, PressureSolver, Projection, ResidualNorm,
+    SimConfig, SolveReport, Solver, Stagger,
+};
+use rayon::ThreadPoolBuilder;
+
+/// Pseudo-random number in `[-1, 1)` for the `n`-th draw.
+fn noise(n: usize) -> f32 {
+    let mut h = (n as u32).wrapping_mul(0x9E37_79B9);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 13;
+    (h >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+}
+
+/// A walled box of `width` x `height` cells with random velocities on the faces between its
+/// cells, returned as `(u, v, s)`.
+fn divergent(width: usize, height: usize) -> (Field2, Field2, Field2) {
+    let (nx, ny) = (width + 2, height + 2);
+    let mut u = Field2::new(nx, ny, Stagger::XFace, 0.0);
+    let mut v = Field2::new(nx, ny, Stagger::YFace, 0.0);
+    let mut s = Field2::new(nx, ny, Stagger::Center, 0.0);
+    for (i, j) in s.interior() {
+        s[(i, j)] = 1.0;
+        let k = j * nx + i;
+        if i > 1 {
+            u[(i, j)] = noise(2 * k);
+        }
+        if j > 1 {
+            v[(i, j)] = noise(2 * k + 1);
+        }
+    }
+    (u, v, s)
+}
+
+/// Projects `u` and `v` with `solver` down to `tolerance` in the `residual_norm` and returns the
+/// report and the pressure.
+fn project(
+    solver: &mut dyn PressureSolver,
+    u: &mut Field2,
+    v: &mut Field2,
+    s: &Field2,
+    tolerance: Option<f32>,
+    residual_norm: ResidualNorm,
+) -> (SolveReport, Field2) {
+    let mut p = Field2::new(s.nx(), s.ny(), Stagger::Center, 0.0);
+    let report = solver.project(Projection {
+        u,
+        v,
+        s,
+        phi: None,
+        p: &mut p,
+        size: 0.1,
+        dt: 0.01,
+        density: 1000.0,
+        tolerance,
+        residual_norm,
+        max_iterations: 10_000,
+    });
+    (report, p)
+}
+
+#[test]
+fn multigrid_cycles_do_not_grow_with_resolution_of_thin_domains() {
+    let cycles = |width| {
+        let (mut u, mut v, s) = divergent(width, 16);
+        let mut solver = Solver::Multigrid(Cycle::V).build(&SimConfig::default());
+        let (report, _) = project(solver.as_mut(), &mut u, &mut v, &s, None, ResidualNorm::Max);
+        report.iterations
+    };
+    let (coarse, fine) = (cycles(128), cycles(512));
+    assert!(
+        fine <= coarse + 2,
+        "{coarse} cycles at 128 cells, {fine} at 512"
+    );
+}
+
+/// Every built-in solver.
+fn solvers() -> [Solver; 6] {
+    [
+        Solver::Sor,
+        Solver::Jacobi,
+        Solver::RedBlack,
+        Solver::Pcg,
+        Solver::Multigrid(Cycle::V),
+        Solver::Multigrid(Cycle::W),
+    ]
+}
+
+#[test]
+fn solvers_reach_the_tolerance() {
+    for solver in solvers() {
+        for norm in [ResidualNorm::Max, ResidualNorm::L2] {
+            let (mut u, mut v, s) = divergent(32, 24);
+            let mut solver = solver.build(&SimConfig::default());
+            let (report, _) = project(solver.as_mut(), &mut u, &mut v, &s, Some(1e-3), norm);
+            assert!(report.converged, "{} {norm:?}", solver.name());
+            assert!(report.residual(norm) < 1e-3, "{} {norm:?}", solver.name());
+        }
+    }
+}
+
+#[test]
+fn red_black_does_not_depend_on_the_number_of_threads() {
+    let run = |threads| {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let (mut u, mut v, s) = divergent(48, 40);
+        let mut solver = Solver::RedBlack.build(&SimConfig::default());
+        let (_, p) =
+            pool.install(|| project(solver.as_mut(), &mut u, &mut v, &s, None, ResidualNorm::Max));
+        (u, v, p)
+    };
+    let (one, four) = (run(1), run(4));
+    assert_eq!(one.0.data(), four.0.data());
+    assert_eq!(one.1.data(), four.1.data());
+    assert_eq!(one.2.data(), four.2.data());
+}
+
+/// `p` over the fluid cells of `s` with its mean removed, since walls all around leave the
+/// pressure undetermined up to a constant.
+fn relative_pressure(p: &Field2, s: &Field2) -> Vec<f32> {
+    let fluid: Vec<_> = p.interior_values().filter(|&(c, _)| s[c] != 0.0).collect();
+    let mean = fluid.iter().map(|&(_, p)| p).sum::<f32>() / fluid.len() as f32;
+    fluid.iter().map(|&(_, p)| p - mean).collect()
+}
+
+#[test]
+fn pcg_and_multigrid_agree_with_sor_on_the_pressure() {
+    let pressure = |solver: Solver| {
+        let (mut u, mut v, s) = divergent(32, 24);
+        let mut solver = solver.build(&SimConfig::default());
+        let (_, p) = project(
+            solver.as_mut(),
+            &mut u,
+            &mut v,
+            &s,
+            Some(1e-5),
+            ResidualNorm::Max,
+        );
+        relative_pressure(&p, &s)
+    };
+    let sor = pressure(Solver::Sor);
+    let scale = sor.iter().fold(0.0f32, |a, p| a.max(p.abs()));
+    for solver in [
+        Solver::Pcg,
+        Solver::Multigrid(Cycle::V),
+        Solver::Multigrid(Cycle::W),
+    ] {
+        let error = pressure(solver)
+            .iter()
+            .zip(&sor)
+            .fold(0.0f32, |a, (p, q)| a.max((p - q).abs()));
+        assert!(error < 1e-3 * scale, "{solver:?}: {error} of {scale}");
+    }
+}
+
+#[test]
+fn multigrid_converges_in_sealed_pockets() {
+    // The default disk seals a narrow tank into a pocket above and one below it
+    let config = SimConfig {
+        solver: Solver::Multigrid(Cycle::V),
+        tolerance: Some(1e-3),
+        boundaries: Boundaries::walls(),
+        ..SimConfig::default()
+    };
+    let mut grid = Grid::new(6, 100, 0.01, config).unwrap();
+    for _ in 0..10 {
+        let report = grid.step(0.01);
+        assert!(report.converged, "{report:?}");
+    }
+    let speed = grid.max_velocity();
+    assert!(speed.is_finite() && speed < 1.0, "{speed}");
+}
+
+#[test]
+fn multigrid_converges_with_open_sides() {
+    let open_top = Boundaries {
+        top: BoundaryCondition::Open,
+        ..Boundaries::walls()
+    };
+    let open_outlet = Boundaries {
+        right: BoundaryCondition::Open,
+        ..Boundaries::default()
+    };
+    for boundaries in [open_top, open_outlet] {
+        let config = SimConfig {
+            solver: Solver::Multigrid(Cycle::V),
+            tolerance: Some(1e-3),
+            boundaries,
+            ..SimConfig::default()
+        };
+        let mut grid = Grid::new(200, 100, 0.01, config).unwrap();
+        for _ in 0..10 {
+            let report = grid.step(0.01);
+            assert!(report.converged, "{boundaries:?}: {report:?}");
+        }
+        let speed = grid.max_velocity();
+        assert!(speed.is_finite() && speed < 10.0, "{boundaries:?}: {speed}");
+    }
+}
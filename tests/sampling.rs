@@ -0,0 +1,137 @@
+use std::f32::consts::PI;
+
+use euler::advect::{self, Path};
+use euler::{Backtrace, Boundary, Field2, Interpolation, Sampler, Scheme, Stagger};
+use macroquad::prelude::*;
+
+/// Interior cells along each side of the test grids.
+const N: usize = 64;
+
+fn fluid() -> Field2 {
+    Field2::new(N + 2, N + 2, Stagger::Center, 1.0)
+}
+
+/// Rigid counterclockwise rotation around the center of the grid, one turn per `period` seconds.
+fn rotation(period: f32) -> (Field2, Field2) {
+    let center = (N as f32 + 1.0) / 2.0;
+    let omega = 2.0 * PI / period;
+    let mut u = Field2::new(N + 2, N + 2, Stagger::XFace, 0.0);
+    let mut v = Field2::new(N + 2, N + 2, Stagger::YFace, 0.0);
+    for j in 0..N + 2 {
+        for i in 0..N + 2 {
+            let (x, y) = (i as f32, j as f32);
+            u[(i, j)] = -omega * (y - center);
+            v[(i, j)] = omega * (x - center);
+        }
+    }
+    (u, v)
+}
+
+fn blob() -> Field2 {
+    let center = vec2((N as f32 + 1.0) / 2.0 + 14.0, (N as f32 + 1.0) / 2.0);
+    let mut rho = Field2::new(N + 2, N + 2, Stagger::Center, 0.0);
+    for (i, j) in rho.interior() {
+        let d = vec2(i as f32, j as f32).distance_squared(center);
+        rho[(i, j)] = (-d / (2.0 * 3.0 * 3.0)).exp();
+    }
+    rho
+}
+
+/// Advects the blob through one full turn and returns the result.
+fn rotate_blob(interpolation: Interpolation) -> Field2 {
+    let steps = 200;
+    let (u, v) = rotation(steps as f32);
+    let s = fluid();
+    let path = Path {
+        backtrace: Backtrace::Rk2,
+        u: &u,
+        v: &v,
+        s: &s,
+        boundary: Boundary::Clamp,
+        interpolation,
+    };
+    let velocity = |i: usize, j: usize| {
+        let inside = (1..=N).contains(&i) && (1..=N).contains(&j);
+        inside.then(|| {
+            vec2(
+                (u[(i, j)] + u[(i + 1, j)]) / 2.0,
+                (v[(i, j)] + v[(i, j + 1)]) / 2.0,
+            )
+        })
+    };
+
+    let mut rho = blob();
+    for _ in 0..steps {
+        advect::advect(&mut rho, Scheme::SemiLagrangian, &path, &velocity, 1.0);
+    }
+    rho
+}
+
+fn peak(field: &Field2) -> f32 {
+    field
+        .data()
+        .iter()
+        .fold(f32::NEG_INFINITY, |a, &b| a.max(b))
+}
+
+fn error(field: &Field2, exact: &Field2) -> f32 {
+    let sum: f32 = field
+        .data()
+        .iter()
+        .zip(exact.data())
+        .map(|(a, b)| (a - b) * (a - b))
+        .sum();
+    sum.sqrt()
+}
+
+#[test]
+fn staggered_samples_are_reproduced() {
+    let s = fluid();
+    let (u, v) = rotation(100.0);
+    let u_sampler = Sampler::new(&u, &s, Boundary::Clamp, Interpolation::Linear);
+    let v_sampler = Sampler::new(&v, &s, Boundary::Clamp, Interpolation::Linear);
+    // The left face of cell (10, 20) and the bottom face of cell (30, 40).
+    assert_eq!(u_sampler.at(vec2(9.5, 20.0)), u[(10, 20)]);
+    assert_eq!(v_sampler.at(vec2(30.0, 39.5)), v[(30, 40)]);
+}
+
+#[test]
+fn cubic_kernels_reproduce_linear_fields() {
+    let s = fluid();
+    let mut field = Field2::new(N + 2, N + 2, Stagger::Center, 0.0);
+    for j in 0..N + 2 {
+        for i in 0..N + 2 {
+            field[(i, j)] = 2.0 * i as f32 - 0.5 * j as f32;
+        }
+    }
+    for interpolation in [Interpolation::CatmullRom, Interpolation::MonotoneCubic] {
+        let sampler = Sampler::new(&field, &s, Boundary::Clamp, interpolation);
+        let value = sampler.at(vec2(20.3, 31.7));
+        assert!(
+            (value - (2.0 * 20.3 - 0.5 * 31.7)).abs() < 1e-4,
+            "{interpolation:?}"
+        );
+    }
+}
+
+#[test]
+fn cubic_kernels_dissipate_less_than_linear() {
+    let exact = blob();
+    let linear = rotate_blob(Interpolation::Linear);
+    for interpolation in [Interpolation::CatmullRom, Interpolation::MonotoneCubic] {
+        let cubic = rotate_blob(interpolation);
+        assert!(peak(&cubic) > peak(&linear), "{interpolation:?}");
+        assert!(
+            error(&cubic, &exact) < error(&linear, &exact),
+            "{interpolation:?}"
+        );
+    }
+}
+
+#[test]
+fn monotone_cubic_does_not_overshoot() {
+    let exact = blob();
+    let rho = rotate_blob(Interpolation::MonotoneCubic);
+    assert!(peak(&rho) <= peak(&exact));
+    assert!(rho.data().iter().all(|&value| value >= 0.0));
+}
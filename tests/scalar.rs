@@ -0,0 +1,76 @@
+use euler::{Boundaries, Disk, Field2, Grid, Scalar, Side, SimConfig};
+use macroquad::prelude::*;
+
+const SIZE: usize = 64;
+
+/// A closed box of fluid at rest with 1 cm cells carrying `scalar`.
+fn still(scalar: Scalar) -> Grid {
+    let config = SimConfig {
+        gravity: 0.0,
+        obstacle: None,
+        boundaries: Boundaries::walls(),
+        scalars: vec![scalar],
+        ..SimConfig::default()
+    };
+    Grid::new(SIZE, SIZE, 0.01, config).unwrap()
+}
+
+/// Variance of `field` along x around its centroid, in cells².
+fn variance(field: &Field2) -> f32 {
+    let (mut mass, mut first, mut second) = (0.0, 0.0, 0.0);
+    for ((i, _), value) in field.interior_values() {
+        let x = i as f32;
+        mass += value;
+        first += value * x;
+        second += value * x * x;
+    }
+    let mean = first / mass;
+    second / mass - mean * mean
+}
+
+#[test]
+fn diffusion_spreads_a_blob_at_the_analytic_rate() {
+    let blob = Disk {
+        center: vec2(0.5, 0.5),
+        radius: 4.0,
+    };
+    let mut grid = still(
+        Scalar::new("dye", RED)
+            .with_diffusion(1e-3)
+            .with_initial(blob, 1.0),
+    );
+    let before = variance(grid.scalar("dye").unwrap());
+    for _ in 0..100 {
+        grid.step(0.01);
+    }
+
+    // Each backward Euler step adds 2 k dt / h² = 0.2 cells² to the variance
+    let spread = variance(grid.scalar("dye").unwrap()) - before;
+    assert!(
+        (spread - 20.0).abs() < 0.2,
+        "variance grew by {spread} cells²"
+    );
+}
+
+#[test]
+fn emitters_hold_their_value() {
+    let mut grid = still(
+        Scalar::new("heat", RED)
+            .with_diffusion(1e-3)
+            .with_emitter(Side::Left, 1.0),
+    );
+    for _ in 0..100 {
+        grid.step(0.01);
+    }
+    let heat = grid.scalar("heat").unwrap();
+    for j in 1..=SIZE {
+        assert!(
+            (heat[(1, j)] - 1.0).abs() < 0.1,
+            "{} in row {j}",
+            heat[(1, j)]
+        );
+        let profile: Vec<_> = (1..=SIZE).map(|i| heat[(i, j)]).collect();
+        assert!(profile.windows(2).all(|w| w[0] >= w[1]), "{profile:?}");
+    }
+    assert!(heat[(8, SIZE / 2)] > 0.1);
+}
@@ -0,0 +1,102 @@
+use std::fs;
+use std::path::Path;
+
+use euler::{Cycle, Scene, Solver};
+
+/// A scene of 10x10 cells with `extra` appended to the file.
+fn parse(extra: &str) -> Result<Scene, String> {
+    let text = format!("[domain]\nwidth = 10\nheight = 10\ncell_size = 0.1\n{extra}");
+    Scene::parse(&text, Path::new("")).map_err(|error| error.to_string())
+}
+
+#[test]
+fn scene_files_are_valid() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+    let mut count = 0;
+    for path in fs::read_dir(dir).unwrap() {
+        let path = path.unwrap().path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            let scene = Scene::load(&path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+            let mut grid = scene.grid().unwrap();
+            grid.step(0.01);
+            count += 1;
+        }
+    }
+    assert!(count >= 2);
+}
+
+#[test]
+fn invalid_scenes_are_rejected_with_a_message_naming_the_entry() {
+    let unknown_key = parse("[physics]\ngravit = 1.0").unwrap_err();
+    assert!(
+        unknown_key.contains("unknown field `gravit`"),
+        "{unknown_key}"
+    );
+
+    let text = "[domain]\nwidth = 10\nheight = 10\ncell_size = -0.1";
+    let negative_size = Scene::parse(text, Path::new("")).unwrap_err().to_string();
+    assert_eq!(
+        negative_size,
+        "invalid scene: cell size must be positive, got -0.1"
+    );
+
+    let unknown_scalar = parse("[buoyancy]\ntemperature = \"heat\"").unwrap_err();
+    assert_eq!(unknown_scalar, "invalid scene: no scalar is named \"heat\"");
+
+    let no_outlet = parse("[boundaries]\nright = \"wall\"").unwrap_err();
+    assert_eq!(
+        no_outlet,
+        "invalid scene: Left boundary: an inflow needs an outflow or open side to leave through"
+    );
+}
+
+#[test]
+fn fields_start_with_their_initial_values() {
+    let scene = parse(
+        r#"
+[boundaries]
+left = "wall"
+right = "wall"
+
+[[scalars]]
+name = "dye"
+color = [1.0, 0.0, 0.0]
+initial = [{ center = [0.5, 0.5], radius = 2.0, value = 0.5 }]
+
+[[initial_velocity]]
+wall = "bottom"
+velocity = [1.0, 0.0]
+"#,
+    )
+    .unwrap();
+    let grid = scene.grid().unwrap();
+    let dye = grid.scalar("dye").unwrap();
+    assert_eq!(dye[(5, 5)], 0.5);
+    assert_eq!(dye[(1, 1)], 0.0);
+    // Faces between fluid cells of the bottom row move, the walls do not.
+    assert_eq!(grid.u()[(5, 1)], 1.0);
+    assert_eq!(grid.u()[(1, 1)], 0.0);
+    assert_eq!(grid.u()[(5, 2)], 0.0);
+
+    let outside = "[[initial_velocity]]\ncenter = [0.5, 1.5]\nradius = 2.0\nvelocity = [1.0, 0.0]";
+    assert!(parse(outside)
+        .unwrap_err()
+        .contains("must be finite and lie within"));
+}
+
+#[test]
+fn multigrid_takes_an_optional_cycle() {
+    let solver = |section| parse(section).map(|scene| scene.config.solver);
+    let multigrid = "[solver]\nmethod = \"multigrid\"";
+    assert_eq!(solver(multigrid), Ok(Solver::Multigrid(Cycle::V)));
+    let w = format!("{multigrid}\ncycle = \"w\"");
+    assert_eq!(solver(&w), Ok(Solver::Multigrid(Cycle::W)));
+    let pcg = "[solver]\nmethod = \"pcg\"\ncycle = \"w\"";
+    assert_eq!(
+        solver(pcg),
+        Err("solver.cycle: only the multigrid method takes a cycle".to_string())
+    );
+}
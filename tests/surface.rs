@@ -0,0 +1,67 @@
+use euler::{Boundaries, Cycle, Field2, Grid, Liquid, SimConfig, Solver};
+use macroquad::prelude::*;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+
+/// A column of water in the left quarter of a closed tank with 5 cm cells.
+fn dam_break(solver: Solver) -> Grid {
+    let column = Liquid::Rect {
+        min: vec2(0.0, 0.0),
+        max: vec2(0.25, 0.7),
+    };
+    let config = SimConfig {
+        obstacle: None,
+        boundaries: Boundaries::walls(),
+        solver,
+        tolerance: Some(1e-4),
+        iterations: 1000,
+        liquid: vec![column],
+        ..SimConfig::default()
+    };
+    Grid::new(WIDTH, HEIGHT, 0.05, config).unwrap()
+}
+
+/// Number of liquid cells, counting cells cut by the surface in proportion to the level set.
+fn volume(grid: &Grid) -> f32 {
+    let phi = grid.phi().unwrap();
+    phi.interior_values()
+        .map(|(_, phi)| (0.5 - phi / grid.size()).clamp(0.0, 1.0))
+        .sum()
+}
+
+/// Column of the rightmost liquid cell in the bottom row.
+fn front(phi: &Field2) -> usize {
+    (1..=WIDTH)
+        .filter(|&i| phi[(i, 1)] < 0.0)
+        .max()
+        .unwrap_or(0)
+}
+
+#[test]
+fn dam_break_keeps_its_volume_while_the_front_moves_right() {
+    for solver in [Solver::Pcg, Solver::Multigrid(Cycle::V)] {
+        let mut grid = dam_break(solver);
+        let (start, initial) = (front(grid.phi().unwrap()), volume(&grid));
+        let mut previous = start;
+        for _ in 0..25 {
+            let report = grid.advance(0.02);
+            assert!(report.solve.converged, "{solver:?}: {report:?}");
+            let current = front(grid.phi().unwrap());
+            assert!(
+                current >= previous,
+                "{solver:?}: front went back from {previous} to {current}"
+            );
+            previous = current;
+            let change = volume(&grid) / initial - 1.0;
+            assert!(
+                change.abs() < 0.05,
+                "{solver:?}: volume changed by {change}"
+            );
+        }
+        assert!(
+            previous > start + 5,
+            "{solver:?}: front only moved from {start} to {previous}"
+        );
+    }
+}
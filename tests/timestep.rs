@@ -0,0 +1,75 @@
+use euler::timestep::{self, MAX_SUBSTEPS};
+use euler::{FixedStep, Pacing};
+
+/// A parcel accelerating at 50 m/s² from 1 m/s, on cells of 0.1 m with a CFL number of 1.
+struct Parcel {
+    speed: f32,
+    /// The CFL bound of each step taken, with its `dt`.
+    steps: Vec<(f32, f32)>,
+}
+
+fn max_dt(parcel: &Parcel) -> f32 {
+    timestep::cfl_dt(parcel.speed, 0.1, 1.0)
+}
+
+fn step(parcel: &mut Parcel, dt: f32) {
+    parcel.steps.push((dt, max_dt(parcel)));
+    parcel.speed += 50.0 * dt;
+}
+
+#[test]
+fn substeps_add_up_to_the_frame_and_respect_the_cfl_bound() {
+    let mut parcel = Parcel {
+        speed: 1.0,
+        steps: Vec::new(),
+    };
+    let substeps = timestep::advance(&mut parcel, 0.25, max_dt, step);
+
+    assert!(!substeps.truncated);
+    assert_eq!(substeps.count, parcel.steps.len());
+    assert!(substeps.count > 1);
+    let total: f32 = parcel.steps.iter().map(|&(dt, _)| dt).sum();
+    assert!((total - 0.25).abs() < 1e-6, "{total}");
+    assert!((substeps.time - 0.25).abs() < 1e-6);
+    for &(dt, bound) in &parcel.steps {
+        assert!(dt <= bound, "{dt} > {bound}");
+    }
+}
+
+#[test]
+fn a_diverging_state_stops_after_the_substep_limit() {
+    let mut steps = 0;
+    let substeps = timestep::advance(&mut steps, 1.0, |_| 1e-9, |steps, _| *steps += 1);
+    assert!(substeps.truncated);
+    assert_eq!(steps, MAX_SUBSTEPS);
+}
+
+#[test]
+fn a_nan_velocity_stops_the_frame() {
+    assert!(timestep::cfl_dt(f32::NAN, 0.1, 1.0).is_nan());
+    let mut steps = 0;
+    let max_dt = |_: &usize| timestep::cfl_dt(f32::NAN, 0.1, 1.0);
+    let substeps = timestep::advance(&mut steps, 1.0, max_dt, |steps, _| *steps += 1);
+    assert!(substeps.truncated);
+    assert_eq!(steps, 0);
+}
+
+#[test]
+fn fixed_steps_carry_the_remainder_over_and_stay_below_the_limit() {
+    let mut clock = FixedStep::new(0.25, 4);
+    assert_eq!(clock.steps(0.375), 1);
+    assert_eq!(clock.steps(0.125), 1);
+    assert_eq!(clock.steps(0.125), 0);
+    assert_eq!(clock.steps(0.125), 1);
+
+    // A long frame runs at most the limit and drops whole steps, but keeps the fraction
+    assert_eq!(clock.steps(2.125), 4);
+    assert_eq!(clock.steps(0.125), 1);
+    for frame in [0.0, 0.01, 0.3, 1.0, 100.0] {
+        assert!(clock.steps(frame) <= 4, "{frame}");
+    }
+
+    clock.toggle_pacing();
+    assert_eq!(clock.pacing, Pacing::FastForward);
+    assert_eq!(clock.steps(0.0), 4);
+}
@@ -0,0 +1,67 @@
+use euler::{Grid, Seed, SimConfig, Tracer, Tracers};
+use macroquad::prelude::*;
+
+const WIDTH: usize = 100;
+const HEIGHT: usize = 50;
+
+/// The default channel around a disk without gravity, with `tracers`.
+fn channel(tracers: Tracers) -> Grid {
+    let config = SimConfig {
+        gravity: 0.0,
+        tracers,
+        ..SimConfig::default()
+    };
+    Grid::new(WIDTH, HEIGHT, 0.01, config).unwrap()
+}
+
+#[test]
+fn tracers_in_a_solid_are_removed() {
+    // A line across the channel through the center of the disk
+    let line = Seed::Line {
+        start: vec2(0.2, 0.02),
+        end: vec2(0.2, 0.98),
+        count: 25,
+    };
+    let mut grid = channel(Tracers {
+        seeds: vec![line],
+        ..Tracers::default()
+    });
+    let fluid = |grid: &Grid, tracer: &Tracer| {
+        let (i, j) = tracer.cell().unwrap();
+        grid.s()[(i, j)] != 0.0
+    };
+    let in_fluid = grid.tracers().iter().filter(|t| fluid(&grid, t)).count();
+    assert!(0 < in_fluid && in_fluid < 25, "{in_fluid}");
+
+    grid.step(0.001);
+    assert_eq!(grid.tracers().len(), in_fluid);
+    assert!(grid.tracers().iter().all(|t| fluid(&grid, t)));
+}
+
+#[test]
+fn trails_and_tracer_count_stay_within_their_limits() {
+    let emitter = Seed::Emitter {
+        position: vec2(0.05, 0.8),
+    };
+    let mut grid = channel(Tracers {
+        seeds: vec![emitter],
+        trail: 5,
+        limit: 20,
+        ..Tracers::default()
+    });
+    for _ in 0..50 {
+        grid.step(0.005);
+        assert!(grid.tracers().len() <= 20);
+        assert!(grid.tracers().iter().all(|tracer| tracer.trail.len() <= 5));
+    }
+    let tracers = grid.tracers();
+    assert_eq!(tracers.len(), 20);
+    // The oldest tracer has moved for more steps than its trail holds
+    assert_eq!(tracers[0].trail.len(), 5);
+    let newest = tracers.last().unwrap();
+    assert!(newest.trail.is_empty());
+    assert_eq!(
+        newest.position,
+        vec2(0.05, 0.8) * vec2(WIDTH as f32, HEIGHT as f32)
+    );
+}
@@ -0,0 +1,44 @@
+use euler::{Boundaries, BoundaryCondition, Grid, Inflow, SimConfig, Solver};
+
+const WIDTH: usize = 48;
+const HEIGHT: usize = 16;
+
+/// A viscous channel without gravity, fed with a uniform inflow of 0.1 m/s on the left. Short
+/// steps keep the splitting error of diffusing before the projection small at the walls.
+fn channel() -> Grid {
+    let config = SimConfig {
+        gravity: 0.0,
+        viscosity: 0.01,
+        obstacle: None,
+        solver: Solver::Pcg,
+        tolerance: Some(1e-5),
+        boundaries: Boundaries {
+            left: BoundaryCondition::Inflow(Inflow::uniform(0.1)),
+            ..Boundaries::default()
+        },
+        ..SimConfig::default()
+    };
+    Grid::new(WIDTH, HEIGHT, 0.01, config).unwrap()
+}
+
+#[test]
+fn uniform_inflow_develops_into_poiseuille_flow() {
+    let mut grid = channel();
+    for _ in 0..800 {
+        grid.step(0.002);
+    }
+
+    // The velocity vanishes at the centers of the wall cells below and above the channel
+    let i = 3 * WIDTH / 4;
+    let parabola = |j: usize| (j * (HEIGHT + 1 - j)) as f32;
+    let total: f32 = (1..=HEIGHT).map(parabola).sum();
+    let flux: f32 = (1..=HEIGHT).map(|j| grid.u()[(i, j)]).sum();
+    for j in 1..=HEIGHT {
+        let exact = flux * parabola(j) / total;
+        let u = grid.u()[(i, j)];
+        assert!(
+            (u - exact).abs() < 1e-3,
+            "{u} instead of {exact} in row {j}"
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use euler::{Boundaries, Disk, Grid, InitialVelocity, Region, SimConfig};
+use macroquad::prelude::*;
+
+/// A closed box without gravity where a jet starts off to the right, rolling up into a pair of
+/// vortices.
+fn jet(vorticity_confinement: f32) -> Grid {
+    let jet = InitialVelocity {
+        region: Region::Disk(Disk {
+            center: vec2(0.3, 0.5),
+            radius: 6.0,
+        }),
+        velocity: vec2(1.0, 0.0),
+    };
+    let config = SimConfig {
+        gravity: 0.0,
+        obstacle: None,
+        boundaries: Boundaries::walls(),
+        initial_velocity: vec![jet],
+        vorticity_confinement,
+        ..SimConfig::default()
+    };
+    Grid::new(64, 48, 0.01, config).unwrap()
+}
+
+/// Integral of the squared curl over the corners between four cells, in 1/s² m².
+fn enstrophy(grid: &Grid) -> f32 {
+    let (u, v, h) = (grid.u(), grid.v(), grid.size());
+    let mut sum = 0.0;
+    for j in 2..=grid.height() {
+        for i in 2..=grid.width() {
+            let curl = (v[(i, j)] - v[(i - 1, j)] - u[(i, j)] + u[(i, j - 1)]) / h;
+            sum += curl * curl * h * h;
+        }
+    }
+    sum
+}
+
+#[test]
+fn confinement_keeps_more_enstrophy() {
+    let run = |epsilon| {
+        let mut grid = jet(epsilon);
+        for _ in 0..100 {
+            grid.step(0.005);
+        }
+        enstrophy(&grid)
+    };
+    let (plain, confined) = (run(0.0), run(1.0));
+    assert!(confined > 1.1 * plain, "{confined} with, {plain} without");
+}